@@ -0,0 +1,12 @@
+// option parsing shared by the binaries
+use std::str::FromStr;
+
+// parses the value that follows an option
+pub fn value<T: FromStr>(args: &mut impl Iterator<Item=String>, option: &str) -> Result<T, String> {
+    let value = args.next().ok_or_else(|| format!("{} needs a value", option))?;
+    parse(option, &value)
+}
+
+pub fn parse<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value for {}: {}", option, value))
+}
@@ -0,0 +1,350 @@
+mod args;
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{ColorType, GenericImage, ImageFormat, RgbImage};
+use spiegel_core::atlas;
+use spiegel_core::catalog::Catalog;
+use spiegel_core::descriptor::TextureDescriptor;
+use spiegel_core::library::{self, PatchOptions};
+use spiegel_core::tags;
+
+use crate::args::value;
+
+const USAGE: &str = "usage: spiegel-library [options] <photo or directory>...
+       spiegel-library --check [options]
+       spiegel-library --atlas [options]
+
+Cuts uniform patches out of photos and adds them to the sample library, as <color>.jpg
+next to the manifest. Directories are searched for images, without recursing.
+
+With --check, measures every sample in the library instead and reports missing and
+unreadable files, colors that differ from the manifest or the file name, and near-duplicates.
+Exits with 1 when it finds any of these.
+
+With --atlas, packs the samples into atlas-<n>.jpg pages, similar colors on the same page, and
+records where each sample is in the manifest, so that the browser loads a few pages with the
+colors it needs instead of every sample.
+
+options:
+  --library <dir>         directory with the sample manifest and textures (default: static/samples)
+  --patch-size <int>      side of the squares cut from the photos, in pixels (default: 512)
+  --size <int>            side of the samples in the library, in pixels (default: 256)
+  --max-variance <float>  color variance above which a patch is not uniform enough (default: 400)
+  --per-photo <int>       most patches taken from one photo (default: 4)
+  --tag <name>            tag for the new samples, like material:wood, can be repeated.
+                          Samples get their brightness, scale and source:photo tags anyway
+  --seamless              blend the edges of the new samples, so that they tile without seams
+  --check                 validate the library instead of adding to it
+  --max-drift <float>     ΔE between the declared and measured color that is reported (default: 2)
+  --duplicates <float>    ΔE below which two samples are reported as near-duplicates (default: 1)
+  --rewrite               with --check, write the measured colors, sizes, tags and texture
+                          descriptors to the manifest and drop the entries whose file is missing or unreadable,
+                          samples whose size changed are left out of the atlas until --atlas
+  --atlas                 rebuild the texture atlas from the sample files
+  --page-size <int>       side of the atlas pages, in pixels (default: 1024)
+  -h, --help              show this message";
+
+// jpeg quality of the stored samples
+const QUALITY: u8 = 90;
+// the atlas is what the browser downloads, at about the size of the separate samples
+const ATLAS_QUALITY: u8 = 80;
+
+#[derive(PartialEq)]
+enum Mode {
+    Add,
+    Check,
+    Atlas,
+}
+
+struct Args {
+    mode: Mode,
+    library: PathBuf,
+    patches: PatchOptions,
+    size: u32,
+    tags: Vec<String>,
+    seamless: bool,
+    max_drift: f32,
+    duplicates: f32,
+    rewrite: bool,
+    page_size: u32,
+    inputs: Vec<PathBuf>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    let result = match args.mode {
+        Mode::Add => add(&args).map(|_| true),
+        Mode::Check => check(&args),
+        Mode::Atlas => atlas(&args).map(|_| true),
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    }
+}
+
+fn read_catalog(manifest: &Path) -> Result<Catalog, Box<dyn Error>> {
+    let json = fs::read_to_string(manifest).map_err(|e| format!("{}: {}", manifest.display(), e))?;
+    Ok(Catalog::from_json(&json).map_err(|e| format!("{}: {}", manifest.display(), e))?)
+}
+
+fn add(args: &Args) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&args.library)?;
+    let manifest = args.library.join("manifest.json");
+    let mut catalog = if manifest.exists() { read_catalog(&manifest)? } else { Catalog::default() };
+
+    let mut tags = vec![format!("{}:photo", tags::SOURCE)];
+    library::set_tags(&mut tags, &args.tags);
+    let mut added = 0;
+    for photo in photos(&args.inputs)? {
+        let src = match image::open(&photo) {
+            Ok(src) => src.to_rgb8(),
+            Err(error) => {
+                eprintln!("{}: {}", photo.display(), error);
+                continue;
+            }
+        };
+        let patches = library::extract_patches(&src, &args.patches);
+        for patch in &patches {
+            let mut sample = library::normalize(&patch.image, args.size);
+            if args.seamless {
+                sample = library::seamless(&sample);
+            }
+            let entry = library::entry(&catalog, &sample, &tags);
+            save_jpeg(&sample, &args.library.join(&entry.file), QUALITY)?;
+            catalog.samples.push(entry);
+        }
+        println!("{} -> {} samples", photo.display(), patches.len());
+        added += patches.len();
+    }
+
+    fs::write(&manifest, catalog.to_json()?)?;
+    println!("added {} samples, {} in {}", added, catalog.samples.len(), manifest.display());
+    if added > 0 && !catalog.atlas.is_empty() {
+        println!("the new samples are not in the atlas yet, run with --atlas to add them");
+    }
+    Ok(())
+}
+
+// reports the problems of the library, returns whether it has none
+fn check(args: &Args) -> Result<bool, Box<dyn Error>> {
+    let manifest = args.library.join("manifest.json");
+    let mut catalog = read_catalog(&manifest)?;
+    let mut problems = 0;
+    let mut keep = Vec::with_capacity(catalog.samples.len());
+    for entry in catalog.samples.iter_mut() {
+        let path = args.library.join(&entry.file);
+        if !path.is_file() {
+            println!("missing     {}: {} does not exist", entry.id, path.display());
+            problems += 1;
+            keep.push(false);
+            continue;
+        }
+        let image = match image::open(&path) {
+            Ok(image) => image.to_rgb8(),
+            Err(error) => {
+                println!("unreadable  {}: {}", entry.id, error);
+                problems += 1;
+                keep.push(false);
+                continue;
+            }
+        };
+        let measured = library::measure(&image);
+        let declared = library::drift(&entry.color(), &measured);
+        let named = library::id_color(&entry.id).map(|color| library::drift(&color, &measured));
+        if declared > args.max_drift || named.is_some_and(|named| named > args.max_drift) {
+            println!(
+                "drift       {}: measured {} (variance {:.1}), manifest {} ΔE {:.2}{}",
+                entry.id,
+                hex(measured.mean),
+                measured.variance,
+                hex(entry.mean),
+                declared,
+                named.map(|named| format!(", name ΔE {:.2}", named)).unwrap_or_default()
+            );
+            problems += 1;
+        }
+        // the atlas page still has the sample at its old size
+        let size = [image.width(), image.height()];
+        let stale = entry.atlas.as_ref().is_some_and(|rect| [rect.width, rect.height] != size);
+        if stale {
+            println!("stale atlas {}: the file is {}x{}, run --atlas to rebuild the pages", entry.id, size[0], size[1]);
+            problems += 1;
+        }
+        if args.rewrite {
+            entry.mean = measured.mean;
+            entry.size = size;
+            if stale {
+                entry.atlas = None;
+            }
+            entry.variance = Some(measured.variance);
+            library::set_tags(&mut entry.tags, &library::describe(&image, &measured));
+            entry.descriptor = Some(TextureDescriptor::of_image(&image));
+        }
+        keep.push(true);
+    }
+
+    for page in &catalog.atlas {
+        if !args.library.join(page).is_file() {
+            println!("missing     atlas page {}", page);
+            problems += 1;
+        }
+    }
+    for (i, j, distance) in library::near_duplicates(&catalog, args.duplicates) {
+        println!("duplicate   {} and {}: ΔE {:.2}", catalog.samples[i].id, catalog.samples[j].id, distance);
+        problems += 1;
+    }
+    println!("{} samples, {} problems", catalog.samples.len(), problems);
+
+    if args.rewrite {
+        let mut keep = keep.into_iter();
+        catalog.samples.retain(|_| keep.next().unwrap());
+        fs::write(&manifest, catalog.to_json()?)?;
+        println!("rewrote {} with {} samples", manifest.display(), catalog.samples.len());
+    }
+    Ok(problems == 0)
+}
+
+// packs the sample files into pages, one page at a time to keep the memory down
+fn atlas(args: &Args) -> Result<(), Box<dyn Error>> {
+    let manifest = args.library.join("manifest.json");
+    let mut catalog = read_catalog(&manifest)?;
+    let ids: Vec<&str> = catalog.samples.iter().map(|entry| entry.id.as_str()).collect();
+    let sizes: Vec<[u32; 2]> = catalog.samples.iter().map(|entry| entry.size).collect();
+    let means: Vec<[u8; 3]> = catalog.samples.iter().map(|entry| entry.mean).collect();
+    let rects = atlas::pack(&ids, &sizes, &means, args.page_size)?;
+    let pages = rects.iter().map(|rect| rect.page + 1).max().unwrap_or(0);
+
+    let mut names = vec![];
+    for page in 0..pages {
+        let on_page: Vec<usize> = (0..rects.len()).filter(|i| rects[*i].page == page).collect();
+        let width = on_page.iter().map(|i| rects[*i].right()).max().unwrap_or(0);
+        let height = on_page.iter().map(|i| rects[*i].bottom()).max().unwrap_or(0);
+        let mut image = RgbImage::new(width, height);
+        for i in on_page {
+            let entry = &catalog.samples[i];
+            let sample = image::open(args.library.join(&entry.file))
+                .map_err(|e| format!("{}: {}", entry.id, e))?
+                .to_rgb8();
+            if sample.dimensions() != (rects[i].width, rects[i].height) {
+                return Err(format!("{}: size differs from the manifest, run --check --rewrite first", entry.id).into());
+            }
+            image.copy_from(&sample, rects[i].x, rects[i].y)?;
+        }
+        let name = format!("atlas-{}.jpg", page);
+        save_jpeg(&image, &args.library.join(&name), ATLAS_QUALITY)?;
+        println!("{} -> {}x{}", name, width, height);
+        names.push(name);
+    }
+
+    // pages of an earlier, larger atlas
+    for stale in catalog.atlas.iter().filter(|name| !names.contains(name)) {
+        fs::remove_file(args.library.join(stale))?;
+    }
+    for (entry, rect) in catalog.samples.iter_mut().zip(rects) {
+        entry.atlas = Some(rect);
+    }
+    catalog.atlas = names;
+    fs::write(&manifest, catalog.to_json()?)?;
+    println!("packed {} samples on {} pages", catalog.samples.len(), pages);
+    Ok(())
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// the inputs, with directories replaced by the images in them
+fn photos(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut photos = vec![];
+    for input in inputs {
+        if input.is_dir() {
+            let mut found: Vec<PathBuf> = fs::read_dir(input)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file() && ImageFormat::from_path(path).is_ok())
+                .collect();
+            found.sort();
+            photos.extend(found);
+        } else {
+            photos.push(input.clone());
+        }
+    }
+    Ok(photos)
+}
+
+fn save_jpeg(image: &RgbImage, path: &Path, quality: u8) -> Result<(), Box<dyn Error>> {
+    let mut file = BufWriter::new(File::create(path)?);
+    JpegEncoder::new_with_quality(&mut file, quality).encode(image.as_raw(), image.width(), image.height(), ColorType::Rgb8)?;
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item=String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        mode: Mode::Add,
+        library: PathBuf::from("static/samples"),
+        patches: PatchOptions::default(),
+        size: 256,
+        tags: vec![],
+        seamless: false,
+        max_drift: 2.0,
+        duplicates: 1.0,
+        rewrite: false,
+        page_size: 1024,
+        inputs: vec![],
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--library" => parsed.library = value(&mut args, &arg)?,
+            "--patch-size" => parsed.patches.size = value(&mut args, &arg)?,
+            "--size" => parsed.size = value(&mut args, &arg)?,
+            "--max-variance" => parsed.patches.max_variance = value(&mut args, &arg)?,
+            "--per-photo" => parsed.patches.max_count = value(&mut args, &arg)?,
+            "--tag" => parsed.tags.push(value(&mut args, &arg)?),
+            "--seamless" => parsed.seamless = true,
+            "--check" => parsed.mode = Mode::Check,
+            "--atlas" => parsed.mode = Mode::Atlas,
+            "--page-size" => parsed.page_size = value(&mut args, &arg)?,
+            "--max-drift" => parsed.max_drift = value(&mut args, &arg)?,
+            "--duplicates" => parsed.duplicates = value(&mut args, &arg)?,
+            "--rewrite" => parsed.rewrite = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => parsed.inputs.push(PathBuf::from(arg)),
+        }
+    }
+    if parsed.mode != Mode::Add && !parsed.inputs.is_empty() {
+        return Err("--check and --atlas take no photos".to_owned());
+    }
+    if parsed.seamless && parsed.mode != Mode::Add {
+        return Err("--seamless is for adding samples".to_owned());
+    }
+    if parsed.rewrite && parsed.mode != Mode::Check {
+        return Err("--rewrite needs --check".to_owned());
+    }
+    if parsed.mode == Mode::Add && parsed.inputs.is_empty() {
+        return Err("no input photos".to_owned());
+    }
+    if parsed.size == 0 || parsed.patches.size == 0 || parsed.page_size == 0 {
+        return Err("sizes must be positive".to_owned());
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,271 @@
+mod args;
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use futures::executor::block_on;
+use serde_json::Value;
+use spiegel_core::error::SpiegelError;
+use spiegel_core::filter::{FilterRegistry, MergeRegions, SampleFill};
+use spiegel_core::pack::{PackWeight, PACKS_FILE};
+use spiegel_core::pipeline::{Pipeline, Stage};
+use spiegel_core::provider::{open_packs, DirectoryProvider, ProceduralProvider, SampleProvider, SeamlessProvider};
+use spiegel_core::samples::{SampleStore, DEFAULT_BUDGET};
+use spiegel_core::tags::TagRule;
+
+use crate::args::{parse, value};
+
+const USAGE: &str = "usage: spiegel [options] <image>...
+
+Applies the spiegel filter to every image and writes the result as <name>-spiegel.png
+
+options:
+  --samples <dir>         directory with a packs.json, or with the manifest and textures of
+                          a single library (default: static)
+  --pack <name[:weight]>  use this sample pack, repeat for more packs. The samples of a pack
+                          with weight 2 win over those up to twice as close in a pack with
+                          weight 1 (default: all packs, with weight 1)
+  --procedural            generate the textures instead of using the sample library
+  --seamless              blend the edges of every sample as it is loaded, so that it tiles
+                          without seams
+  --cache <MiB>           memory for decoded samples, shared by all images (default: 256)
+  --output <dir>          directory for the results (default: next to each image)
+  --pipeline <file>       json recipe to run instead of the default pipeline,
+                          the options below change the matching stages in it
+  --sigma <float>         sigma of the gaussian blur (default: 2.0)
+  --median-radius <int>   radius of the median filter (default: 2)
+  --colors <int>          number of colors after quantization (default: 256)
+  --metric <name>         color distance for matching samples and regions: rgb, weighted_rgb,
+                          cie76, ciede2000 or oklab (default: rgb)
+  --tolerance <float>     maximum color distance within a region, per channel with rgb
+                          (default: 4)
+  --connectivity <4|8>    whether pixels that only touch at a corner are in the same region,
+                          with 8 they are (default: 4)
+  --min-area <int>        merge regions with fewer pixels into a neighbour before the sample
+                          fill (default: no merging, 16 with --merge-by)
+  --merge-by <name>       merge small regions into a neighbour: the closest in color (color),
+                          or the one with the longest shared border (border)
+  --texture <float>       how much the texture of a region counts next to its color when
+                          choosing its sample, 0 to match on color alone (default: 0)
+  --choices <int>         pick the sample of a region at random among this many closest,
+                          the closer ones more often (default: 1, always the closest)
+  --spread <float>        with choices, only pick samples at most this much further away
+                          than the closest (default: 8)
+  --seed <int>            seed for the random choices, the same seed gives the same result
+                          (default: 0)
+  --distinct              give touching regions different samples where a close one is left
+  --anchor <name>         where the texture of a region starts: grid for one grid of tiles
+                          over the whole image, bbox for the corner of the region, or
+                          centroid for its center (default: grid)
+  --offset                shift the texture of every region at random
+  --rotate                turn the texture of every region at random by quarter turns
+  --flip                  mirror the texture of some regions at random
+  --tiling <name>         how textures continue past their edges: wrap, mirror or clamp
+                          (default: wrap)
+  --include <tag>         only use samples with this tag, like material:wood or just wood,
+                          with several, samples need one of them
+  --exclude <tag>         never use samples with this tag, can be repeated
+  -h, --help              show this message";
+
+struct Args {
+    samples: PathBuf,
+    packs: Vec<PackWeight>,
+    procedural: bool,
+    seamless: bool,
+    // memory budget of the sample cache, in MiB
+    cache: usize,
+    output: Option<PathBuf>,
+    pipeline: Pipeline,
+    inputs: Vec<PathBuf>,
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut provider: Box<dyn SampleProvider> = if args.procedural {
+        Box::new(ProceduralProvider::default())
+    } else {
+        match open_samples(&args) {
+            Ok(provider) => provider,
+            Err(error) => {
+                eprintln!("{}: {}", args.samples.display(), error);
+                process::exit(1);
+            }
+        }
+    };
+
+    if args.seamless {
+        provider = Box::new(SeamlessProvider::new(provider));
+    }
+
+    let mut store = SampleStore::new(args.cache << 20);
+    let mut failed = false;
+    for input in &args.inputs {
+        match stylize(input, provider.as_ref(), &mut store, &args) {
+            Ok(output) => println!("{} -> {}", input.display(), output.display()),
+            Err(error) => {
+                eprintln!("{}: {}", input.display(), error);
+                failed = true;
+            }
+        }
+    }
+    let stats = store.stats();
+    println!("samples: {} loaded, {} from cache, {} evicted", stats.misses, stats.hits, stats.evictions);
+    if failed {
+        process::exit(1);
+    }
+}
+
+// the selected packs when the directory has a packs.json, or the library in it
+fn open_samples(args: &Args) -> Result<Box<dyn SampleProvider>, SpiegelError> {
+    if args.samples.join(PACKS_FILE).exists() {
+        Ok(Box::new(open_packs(&args.samples, &args.packs)?))
+    } else if let Some(pack) = args.packs.first() {
+        Err(SpiegelError::UnknownPack(pack.name.clone()))
+    } else {
+        Ok(Box::new(DirectoryProvider::open(&args.samples)?))
+    }
+}
+
+fn stylize(input: &Path, provider: &dyn SampleProvider, store: &mut SampleStore, args: &Args) -> Result<PathBuf, Box<dyn Error>> {
+    let src = image::open(input)?.to_rgb8();
+    let out = block_on(args.pipeline.apply(&src, provider, store, &FilterRegistry::default()))?;
+
+    let stem = input.file_stem().ok_or("input is not a file")?.to_string_lossy();
+    let dir = match &args.output {
+        Some(dir) => dir.as_path(),
+        None => input.parent().unwrap_or_else(|| Path::new(".")),
+    };
+    let output = dir.join(format!("{}-spiegel.png", stem));
+    out.save(&output)?;
+    Ok(output)
+}
+
+fn parse_args(mut args: impl Iterator<Item=String>) -> Result<Args, String> {
+    let mut parsed = Args {
+        samples: PathBuf::from("static"),
+        packs: vec![],
+        procedural: false,
+        seamless: false,
+        cache: DEFAULT_BUDGET >> 20,
+        output: None,
+        pipeline: Pipeline::default(),
+        inputs: vec![],
+    };
+    // stage options are applied after the pipeline is known
+    let mut overrides: Vec<(String, String)> = vec![];
+    let mut rule = TagRule::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--samples" => parsed.samples = value(&mut args, &arg)?,
+            "--pack" => parsed.packs.push(value(&mut args, &arg)?),
+            "--procedural" => parsed.procedural = true,
+            "--seamless" => parsed.seamless = true,
+            "--cache" => parsed.cache = value(&mut args, &arg)?,
+            "--output" => parsed.output = Some(value(&mut args, &arg)?),
+            "--pipeline" => {
+                let path: PathBuf = value(&mut args, &arg)?;
+                let json = fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+                parsed.pipeline = Pipeline::from_json(&json).map_err(|e| format!("invalid pipeline {}: {}", path.display(), e))?;
+            }
+            "--distinct" | "--offset" | "--rotate" | "--flip" => overrides.push((arg, String::new())),
+            "--include" => rule.include.push(value(&mut args, &arg)?),
+            "--exclude" => rule.exclude.push(value(&mut args, &arg)?),
+            "--sigma" | "--median-radius" | "--colors" | "--metric" | "--tolerance" | "--connectivity"
+            | "--min-area" | "--merge-by" | "--texture" | "--choices" | "--spread" | "--seed"
+            | "--anchor" | "--tiling" => {
+                let value = value(&mut args, &arg)?;
+                overrides.push((arg, value));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => parsed.inputs.push(PathBuf::from(arg)),
+        }
+    }
+    if parsed.inputs.is_empty() {
+        return Err("no input images".to_owned());
+    }
+    if overrides.iter().any(|(option, _)| option == "--min-area" || option == "--merge-by") {
+        add_merge_stage(&mut parsed.pipeline);
+    }
+    for (option, value) in overrides {
+        override_stages(&mut parsed.pipeline, &option, &value)?;
+    }
+    if rule != TagRule::default() {
+        for stage in parsed.pipeline.stages.iter_mut() {
+            if let Stage::SampleFill(fill) = stage {
+                fill.tags.push(rule.clone());
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// Adds a merge stage before the first sample fill, with the regions of that fill, unless the
+// pipeline merges regions already. The default pipeline does not merge them.
+fn add_merge_stage(pipeline: &mut Pipeline) {
+    if pipeline.stages.iter().any(|stage| matches!(stage, Stage::MergeRegions(_))) {
+        return;
+    }
+    let index = pipeline.stages.iter().position(|stage| matches!(stage, Stage::SampleFill(_))).unwrap_or(pipeline.stages.len());
+    let merge = match pipeline.stages.get(index) {
+        Some(Stage::SampleFill(fill)) => MergeRegions {
+            metric: fill.metric,
+            tolerance: fill.tolerance,
+            connectivity: fill.connectivity,
+            ..MergeRegions::default()
+        },
+        _ => MergeRegions::default(),
+    };
+    pipeline.insert(index, Stage::MergeRegions(merge));
+}
+
+// sets the parameter for an option on every stage that has it
+fn override_stages(pipeline: &mut Pipeline, option: &str, value: &str) -> Result<(), String> {
+    // the values of these options are stored by their names in pipelines
+    let name = || Value::String(value.to_owned());
+    let invalid = |_| format!("invalid value for {}: {}", option, value);
+    for stage in pipeline.stages.iter_mut() {
+        match (option, stage) {
+            ("--sigma", Stage::GaussianBlur { sigma }) => *sigma = parse(option, value)?,
+            ("--median-radius", Stage::Median { x_radius, y_radius }) => {
+                *x_radius = parse(option, value)?;
+                *y_radius = *x_radius;
+            }
+            ("--colors", Stage::Quantize { colors }) => *colors = parse(option, value)?,
+            ("--metric", Stage::SampleFill(SampleFill { metric, .. }) | Stage::MergeRegions(MergeRegions { metric, .. })) => {
+                *metric = serde_json::from_value(name()).map_err(invalid)?
+            }
+            ("--tolerance", Stage::SampleFill(SampleFill { tolerance, .. }) | Stage::MergeRegions(MergeRegions { tolerance, .. })) => {
+                *tolerance = parse(option, value)?
+            }
+            ("--connectivity", Stage::SampleFill(SampleFill { connectivity, .. }) | Stage::MergeRegions(MergeRegions { connectivity, .. })) => {
+                *connectivity = serde_json::from_value(name()).map_err(invalid)?
+            }
+            ("--min-area", Stage::MergeRegions(MergeRegions { min_area, .. })) => *min_area = parse(option, value)?,
+            ("--merge-by", Stage::MergeRegions(MergeRegions { by, .. })) => *by = serde_json::from_value(name()).map_err(invalid)?,
+            ("--texture", Stage::SampleFill(SampleFill { texture, .. })) => *texture = parse(option, value)?,
+            ("--choices", Stage::SampleFill(SampleFill { choices, .. })) => *choices = parse(option, value)?,
+            ("--spread", Stage::SampleFill(SampleFill { spread, .. })) => *spread = parse(option, value)?,
+            ("--seed", Stage::SampleFill(SampleFill { seed, .. })) => *seed = parse(option, value)?,
+            ("--distinct", Stage::SampleFill(SampleFill { distinct, .. })) => *distinct = true,
+            ("--anchor", Stage::SampleFill(SampleFill { placement, .. })) => placement.anchor = serde_json::from_value(name()).map_err(invalid)?,
+            ("--tiling", Stage::SampleFill(SampleFill { placement, .. })) => placement.tiling = serde_json::from_value(name()).map_err(invalid)?,
+            ("--offset", Stage::SampleFill(SampleFill { placement, .. })) => placement.offset = true,
+            ("--rotate", Stage::SampleFill(SampleFill { placement, .. })) => placement.rotate = true,
+            ("--flip", Stage::SampleFill(SampleFill { placement, .. })) => placement.flip = true,
+            _ => {}
+        }
+    }
+    Ok(())
+}
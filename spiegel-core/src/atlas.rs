@@ -0,0 +1,149 @@
+use image::{imageops, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpiegelError;
+
+// positions are rounded up to the jpeg block size, so that no block of a page straddles two samples
+const ALIGN: u32 = 16;
+
+// where a sample is in the atlas: a rectangle on one of its pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AtlasRect {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    pub fn right(&self) -> u32 {
+        self.x + self.width
+    }
+}
+
+// Packs the samples on square pages by color: a pipeline mostly needs samples of a few
+// colors, and with the similar ones on the same page, the browser loads only a few small pages
+// for them. The samples are taken in the order of their mean color, and added to the current page
+// as long as it still fits them, a new page starts when it does not.
+// The rects are in the order of the sizes.
+pub fn pack(ids: &[&str], sizes: &[[u32; 2]], means: &[[u8; 3]], page_size: u32) -> Result<Vec<AtlasRect>, SpiegelError> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|i| color_order(means[*i]));
+
+    let mut rects = vec![AtlasRect { page: 0, x: 0, y: 0, width: 0, height: 0 }; sizes.len()];
+    let mut page = 0;
+    let mut on_page: Vec<usize> = vec![];
+    for i in order {
+        let [width, height] = sizes[i];
+        if width > page_size || height > page_size {
+            return Err(SpiegelError::AtlasOverflow(ids[i].to_owned()));
+        }
+        on_page.push(i);
+        if shelves(&on_page, sizes, page_size).is_none() {
+            on_page = vec![i];
+            page += 1;
+        }
+        // the samples already on the page may move to make room for the new one
+        for (i, [x, y]) in shelves(&on_page, sizes, page_size).unwrap_or_default() {
+            let [width, height] = sizes[i];
+            rects[i] = AtlasRect { page, x, y, width, height };
+        }
+    }
+    Ok(rects)
+}
+
+// Shelf packing of one page. The sizes are placed left to right on shelves, tallest first,
+// a new shelf starts when a size does not fit on the current one. Returns the position of
+// every size on the page, or None when the shelves do not fit on it.
+fn shelves(on_page: &[usize], sizes: &[[u32; 2]], page_size: u32) -> Option<Vec<(usize, [u32; 2])>> {
+    let mut order = on_page.to_vec();
+    order.sort_by_key(|i| std::cmp::Reverse(sizes[*i][1]));
+
+    let mut positions = vec![];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let [width, height] = sizes[i];
+        if x + width > page_size {
+            x = 0;
+            y = align(y + shelf_height);
+            shelf_height = 0;
+        }
+        if y + height > page_size {
+            return None;
+        }
+        positions.push((i, [x, y]));
+        x = align(x + width);
+        shelf_height = shelf_height.max(height);
+    }
+    Some(positions)
+}
+
+// Interleaves the bits of the channels, so that colors that are close mostly are close in this
+// order too.
+fn color_order(mean: [u8; 3]) -> u32 {
+    (0..8).rev().fold(0, |key, bit| {
+        mean.iter().fold(key, |key, channel| key << 1 | (*channel as u32 >> bit & 1))
+    })
+}
+
+// the image of a sample, cut out of its page
+pub fn slice(page: &RgbImage, rect: &AtlasRect) -> RgbImage {
+    imageops::crop_imm(page, rect.x, rect.y, rect.width, rect.height).to_image()
+}
+
+fn align(value: u32) -> u32 {
+    value.div_ceil(ALIGN) * ALIGN
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgb;
+
+    use super::*;
+
+    fn overlaps(a: &AtlasRect, b: &AtlasRect) -> bool {
+        a.page == b.page && a.x < b.right() && b.x < a.right() && a.y < b.bottom() && b.y < a.bottom()
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let sizes: Vec<[u32; 2]> = (0..40).map(|i| [20 + i % 7 * 9, 30 + i % 5 * 11]).collect();
+        let ids: Vec<String> = (0..sizes.len()).map(|i| i.to_string()).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let means: Vec<[u8; 3]> = (0..sizes.len()).map(|i| [(i * 37 % 256) as u8, 0x80, (i * 11 % 256) as u8]).collect();
+        let rects = pack(&ids, &sizes, &means, 256).unwrap();
+
+        for (i, rect) in rects.iter().enumerate() {
+            assert_eq!(sizes[i], [rect.width, rect.height]);
+            assert!(rect.right() <= 256 && rect.bottom() <= 256);
+            assert_eq!(0, rect.x % ALIGN);
+            assert_eq!(0, rect.y % ALIGN);
+            assert!(rects[i + 1..].iter().all(|other| !overlaps(rect, other)));
+        }
+        assert!(rects.iter().any(|rect| rect.page > 0));
+
+        assert!(matches!(pack(&["big"], &[[300, 10]], &[[0, 0, 0]], 256), Err(SpiegelError::AtlasOverflow(id)) if id == "big"));
+    }
+
+    #[test]
+    fn packs_similar_colors_on_the_same_page() {
+        // alternately dark and light, four fit on a page
+        let means: Vec<[u8; 3]> = (0..8).map(|i| if i % 2 == 0 { [0x10, 0x10, 0x10] } else { [0xf0, 0xe0, 0xf0] }).collect();
+        let rects = pack(&["0"; 8], &[[64, 64]; 8], &means, 128).unwrap();
+        for (i, rect) in rects.iter().enumerate() {
+            assert_eq!(i % 2, rect.page);
+        }
+    }
+
+    #[test]
+    fn slices_samples() {
+        let page = RgbImage::from_fn(64, 64, |x, y| if x >= 16 && y >= 32 { Rgb([1, 2, 3]) } else { Rgb([0, 0, 0]) });
+        let sample = slice(&page, &AtlasRect { page: 0, x: 16, y: 32, width: 8, height: 4 });
+        assert_eq!(RgbImage::from_pixel(8, 4, Rgb([1, 2, 3])), sample);
+    }
+}
@@ -0,0 +1,151 @@
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+use crate::atlas::AtlasRect;
+use crate::color::DistanceMetric;
+use crate::descriptor::TextureDescriptor;
+use crate::error::SpiegelError;
+use crate::index::SampleIndex;
+use crate::pack::{PackIndex, PackWeight};
+
+// the manifest version this crate reads and writes
+pub const MANIFEST_VERSION: u32 = 1;
+
+// The sample library, as described by the manifest.json next to the sample images.
+// It is loaded at runtime, so the library can change without a recompile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Catalog {
+    pub version: u32,
+    // the pages of the texture atlas, relative to the manifest, when the library has one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub atlas: Vec<String>,
+    pub samples: Vec<SampleEntry>,
+    // the weights of the packs, in catalogs combined from several packs
+    #[serde(skip)]
+    pub packs: Vec<PackWeight>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleEntry {
+    pub id: String,
+    // path of the image, relative to the manifest
+    pub file: String,
+    // measured mean color of the image
+    pub mean: [u8; 3],
+    // width and height of the image
+    pub size: [u32; 2],
+    // measured color variance, for samples added by the library builder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variance: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // what the texture looks like, for matching it with regions beyond their color
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub descriptor: Option<TextureDescriptor>,
+    // where the sample is in the atlas, samples without one are only in their file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atlas: Option<AtlasRect>,
+    // the pack of the sample, in catalogs combined from several packs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
+}
+
+impl SampleEntry {
+    // an entry for the image <id>.jpg
+    pub fn new(id: &str, mean: [u8; 3], size: [u32; 2]) -> Self {
+        Self { id: id.to_owned(), file: format!("{}.jpg", id), mean, size, variance: None, tags: vec![], descriptor: None, atlas: None, pack: None }
+    }
+
+    pub fn color(&self) -> Rgb<u8> {
+        Rgb(self.mean)
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self { version: MANIFEST_VERSION, atlas: vec![], samples: vec![], packs: vec![] }
+    }
+}
+
+impl Catalog {
+    pub fn from_json(json: &str) -> Result<Self, SpiegelError> {
+        let catalog: Catalog = serde_json::from_str(json)?;
+        if catalog.version != MANIFEST_VERSION {
+            return Err(SpiegelError::UnsupportedManifest(catalog.version));
+        }
+        Ok(catalog)
+    }
+
+    // one sample per line, which keeps the (large) manifest readable and diffable
+    pub fn to_json(&self) -> Result<String, SpiegelError> {
+        let mut lines = Vec::with_capacity(self.samples.len());
+        for entry in &self.samples {
+            lines.push(format!("    {}", serde_json::to_string(entry)?));
+        }
+        let atlas = if self.atlas.is_empty() {
+            String::new()
+        } else {
+            format!("  \"atlas\": {},\n", serde_json::to_string(&self.atlas)?)
+        };
+        Ok(format!(
+            "{{\n  \"version\": {},\n{}  \"samples\": [\n{}\n  ]\n}}\n",
+            self.version,
+            atlas,
+            lines.join(",\n")
+        ))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SampleEntry> {
+        self.samples.iter().find(|entry| entry.id == id)
+    }
+
+    // the atlas page of a sample, relative to the manifest
+    pub fn page(&self, entry: &SampleEntry) -> Option<&str> {
+        entry.atlas.and_then(|rect| self.atlas.get(rect.page)).map(String::as_str)
+    }
+
+    // a nearest neighbour index over the sample colors, its indexes are positions in samples
+    pub fn index(&self, metric: DistanceMetric) -> SampleIndex {
+        let colors: Vec<Rgb<u8>> = self.samples.iter().map(SampleEntry::color).collect();
+        SampleIndex::new(&colors, metric)
+    }
+
+    // like index, but the distances are weighted by the packs of the samples
+    pub fn pack_index(&self, metric: DistanceMetric) -> PackIndex {
+        PackIndex::new(self, metric)
+    }
+
+    pub fn weight(&self, pack: &str) -> Option<f32> {
+        self.packs.iter().find(|weight| weight.name == pack).map(|weight| weight.weight)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_roundtrip() {
+        let json = r#"{
+            "version": 1,
+            "atlas": ["atlas-0.jpg"],
+            "samples": [
+                {"id": "12110f", "file": "12110f.jpg", "mean": [18, 18, 16], "size": [338, 328]},
+                {"id": "moss", "file": "moss.jpg", "mean": [80, 100, 40], "size": [64, 64], "tags": ["foliage"],
+                 "atlas": {"page": 0, "x": 0, "y": 0, "width": 64, "height": 64}}
+            ]
+        }"#;
+        let catalog = Catalog::from_json(json).unwrap();
+        assert_eq!(Rgb([18, 18, 16]), catalog.get("12110f").unwrap().color());
+        assert_eq!(vec!["foliage".to_owned()], catalog.get("moss").unwrap().tags);
+        assert_eq!(None, catalog.page(catalog.get("12110f").unwrap()));
+        assert_eq!(Some("atlas-0.jpg"), catalog.page(catalog.get("moss").unwrap()));
+        assert_eq!(catalog, Catalog::from_json(&catalog.to_json().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let result = Catalog::from_json(r#"{"version": 2, "samples": []}"#);
+        assert!(matches!(result, Err(SpiegelError::UnsupportedManifest(2))));
+    }
+}
@@ -0,0 +1,278 @@
+// the matrices are kept with the precision they are published with
+#![allow(clippy::excessive_precision)]
+
+use image::{Pixel, Rgb};
+use serde::{Deserialize, Serialize};
+
+// Color science for matching samples: conversions between sRGB, linear RGB, CIE XYZ (D65),
+// CIELAB and OKLab, and the distance metrics built on them.
+// Colors other than sRGB are plain [f32; 3] triples, in the order of their names.
+
+// reference white D65, for XYZ <-> Lab
+const WHITE: [f32; 3] = [0.95047, 1.0, 1.08883];
+const EPSILON: f32 = 216.0 / 24389.0;
+const KAPPA: f32 = 24389.0 / 27.0;
+
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(channel: f32) -> u8 {
+    let c = channel.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+pub fn to_linear(rgb: &Rgb<u8>) -> [f32; 3] {
+    let c = rgb.channels();
+    [srgb_to_linear(c[0]), srgb_to_linear(c[1]), srgb_to_linear(c[2])]
+}
+
+pub fn from_linear(linear: [f32; 3]) -> Rgb<u8> {
+    Rgb([linear_to_srgb(linear[0]), linear_to_srgb(linear[1]), linear_to_srgb(linear[2])])
+}
+
+pub fn linear_to_xyz([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        0.4124564 * r + 0.3575761 * g + 0.1804375 * b,
+        0.2126729 * r + 0.7151522 * g + 0.0721750 * b,
+        0.0193339 * r + 0.1191920 * g + 0.9503041 * b,
+    ]
+}
+
+pub fn xyz_to_linear([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+}
+
+pub fn xyz_to_lab(xyz: [f32; 3]) -> [f32; 3] {
+    let f = |t: f32| if t > EPSILON { t.cbrt() } else { (KAPPA * t + 16.0) / 116.0 };
+    let fx = f(xyz[0] / WHITE[0]);
+    let fy = f(xyz[1] / WHITE[1]);
+    let fz = f(xyz[2] / WHITE[2]);
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+pub fn lab_to_xyz([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inv = |f: f32| if f * f * f > EPSILON { f * f * f } else { (116.0 * f - 16.0) / KAPPA };
+    let y = if l > KAPPA * EPSILON { fy * fy * fy } else { l / KAPPA };
+    [f_inv(fx) * WHITE[0], y * WHITE[1], f_inv(fz) * WHITE[2]]
+}
+
+pub fn to_lab(rgb: &Rgb<u8>) -> [f32; 3] {
+    xyz_to_lab(linear_to_xyz(to_linear(rgb)))
+}
+
+pub fn from_lab(lab: [f32; 3]) -> Rgb<u8> {
+    from_linear(xyz_to_linear(lab_to_xyz(lab)))
+}
+
+// OKLab, see https://bottosson.github.io/posts/oklab/
+pub fn linear_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+    let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+    let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+pub fn oklab_to_linear([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+    let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+    let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+    let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+}
+
+pub fn to_oklab(rgb: &Rgb<u8>) -> [f32; 3] {
+    linear_to_oklab(to_linear(rgb))
+}
+
+pub fn from_oklab(oklab: [f32; 3]) -> Rgb<u8> {
+    from_linear(oklab_to_linear(oklab))
+}
+
+pub fn euclidean(c1: [f32; 3], c2: [f32; 3]) -> f32 {
+    let d0 = c1[0] - c2[0];
+    let d1 = c1[1] - c2[1];
+    let d2 = c1[2] - c2[2];
+    f32::sqrt(d0 * d0 + d1 * d1 + d2 * d2)
+}
+
+// CIEDE2000 color difference between two Lab colors, computed in f64 for stability around the hue wrap
+pub fn ciede2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let (l1, a1, b1) = (lab1[0] as f64, lab1[1] as f64, lab1[2] as f64);
+    let (l2, a2, b2) = (lab2[0] as f64, lab2[1] as f64, lab2[2] as f64);
+    let pow25_7 = 25f64.powi(7);
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+    let a1 = (1.0 + g) * a1;
+    let a2 = (1.0 + g) * a2;
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+    let hue = |a: f64, b: f64| if a == 0.0 && b == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+    let h1 = hue(a1, b1);
+    let h2 = hue(a2, b2);
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h.to_radians() / 2.0).sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar).to_radians().cos()
+        + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let l = delta_l / s_l;
+    let c = delta_c / s_c;
+    let h = delta_h / s_h;
+    (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+}
+
+// How the difference between two colors is measured, both to pick the closest sample
+// and to decide which pixels belong to a region.
+// Rgb and WeightedRgb are in sRGB units (0-255), the others in their own ΔE units:
+// roughly 1 is a just noticeable difference for Cie76 and Ciede2000, 0.01 for Oklab.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceMetric {
+    // euclidean distance in sRGB
+    #[default]
+    Rgb,
+    // euclidean distance in sRGB with the channels weighted 2, 4, 3 for the sensitivity of the eye
+    WeightedRgb,
+    // euclidean distance in CIELAB
+    Cie76,
+    Ciede2000,
+    // euclidean distance in OKLab
+    Oklab,
+}
+
+impl DistanceMetric {
+    pub fn distance(&self, c1: &Rgb<u8>, c2: &Rgb<u8>) -> f32 {
+        match self {
+            DistanceMetric::Ciede2000 => ciede2000(to_lab(c1), to_lab(c2)),
+            _ => euclidean(self.coordinates(c1), self.coordinates(c2)),
+        }
+    }
+
+    // Whether two colors are close enough to be in the same region. With Rgb every channel
+    // must be within tolerance, as in the original spiegel filter, with the others the distance.
+    pub fn within(&self, c1: &Rgb<u8>, c2: &Rgb<u8>, tolerance: f32) -> bool {
+        match self {
+            DistanceMetric::Rgb => c1.channels().iter().zip(c2.channels()).all(|(a, b)| (a.abs_diff(*b) as f32) < tolerance),
+            _ => self.distance(c1, c2) < tolerance,
+        }
+    }
+
+    // The color in the space the metric works in. For all metrics but Ciede2000
+    // the distance is the euclidean distance between these coordinates.
+    pub fn coordinates(&self, rgb: &Rgb<u8>) -> [f32; 3] {
+        let c = rgb.channels();
+        match self {
+            DistanceMetric::Rgb => [c[0] as f32, c[1] as f32, c[2] as f32],
+            DistanceMetric::WeightedRgb => [
+                c[0] as f32 * std::f32::consts::SQRT_2,
+                c[1] as f32 * 2.0,
+                c[2] as f32 * 3f32.sqrt(),
+            ],
+            DistanceMetric::Cie76 | DistanceMetric::Ciede2000 => to_lab(rgb),
+            DistanceMetric::Oklab => to_oklab(rgb),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(expected: f32, actual: f32, delta: f32) {
+        assert!((expected - actual).abs() <= delta, "expected {} but was {}", expected, actual);
+    }
+
+    #[test]
+    fn white_and_black_in_lab_and_oklab() {
+        let white = to_lab(&Rgb([255, 255, 255]));
+        assert_close(100.0, white[0], 0.01);
+        assert_close(0.0, white[1], 0.01);
+        assert_close(0.0, white[2], 0.01);
+        assert_close(1.0, to_oklab(&Rgb([255, 255, 255]))[0], 0.001);
+        assert_close(0.0, to_lab(&Rgb([0, 0, 0]))[0], 0.01);
+    }
+
+    #[test]
+    fn conversions_roundtrip() {
+        for rgb in [Rgb([0, 0, 0]), Rgb([18, 17, 15]), Rgb([42, 57, 96]), Rgb([240, 231, 136]), Rgb([255, 0, 128])] {
+            assert_eq!(rgb, from_lab(to_lab(&rgb)));
+            assert_eq!(rgb, from_oklab(to_oklab(&rgb)));
+        }
+    }
+
+    #[test]
+    fn ciede2000_matches_reference_data() {
+        // pairs from Sharma, Wu and Dalal, "The CIEDE2000 color-difference formula"
+        assert_close(2.0425, ciede2000([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485]), 0.0001);
+        assert_close(2.3669, ciede2000([50.0, 0.0, 0.0], [50.0, -1.0, 2.0]), 0.0001);
+        assert_close(27.1492, ciede2000([50.0, 2.5, 0.0], [73.0, 25.0, -18.0]), 0.0001);
+        assert_close(1.0000, ciede2000([50.0, 2.5, 0.0], [50.0, 3.1736, 0.5854]), 0.0001);
+        assert_close(0.0, ciede2000([60.0, 10.0, -20.0], [60.0, 10.0, -20.0]), 0.0001);
+    }
+
+    #[test]
+    fn rgb_tolerance_is_per_channel() {
+        let (c1, c2) = (Rgb([100, 100, 100]), Rgb([103, 103, 103]));
+        assert!(DistanceMetric::Rgb.within(&c1, &c2, 4.0));
+        assert!(!DistanceMetric::Rgb.within(&c1, &Rgb([104, 100, 100]), 4.0));
+        assert!(!DistanceMetric::WeightedRgb.within(&c1, &c2, 4.0));
+    }
+}
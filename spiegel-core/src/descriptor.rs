@@ -0,0 +1,155 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+
+pub const HISTOGRAM_BINS: usize = 8;
+// the histogram bins are this wide, around the mean lightness
+const BIN_WIDTH: f32 = 16.0;
+// scales the orientation and histogram differences, which are at most 1 and 2,
+// to about the range of the differences in standard deviation
+const ORIENTATION_SCALE: f32 = 32.0;
+const HISTOGRAM_SCALE: f32 = 16.0;
+
+// What a texture looks like apart from its color: how busy it is, whether it has a direction,
+// and how its lightness is spread around the mean.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextureDescriptor {
+    pub mean: [u8; 3],
+    // the variance of the channels around the mean, averaged over the channels
+    pub variance: f32,
+    // the dominant direction of the gradients, in radians between 0 and π,
+    // and how much it dominates, between 0 for no direction and 1 for stripes
+    pub orientation: f32,
+    pub coherence: f32,
+    // the share of the pixels in each bin of the lightness around the mean, the outer bins
+    // take everything beyond them
+    pub histogram: [f32; HISTOGRAM_BINS],
+}
+
+impl TextureDescriptor {
+    pub fn of_image(img: &RgbImage) -> Self {
+        Self::of_points(img, (0..img.height()).flat_map(|y| (0..img.width()).map(move |x| (x, y))))
+    }
+
+    // the descriptor of some of the pixels of img, like the pixels of a region
+    pub fn of_points(img: &RgbImage, points: impl Iterator<Item = (u32, u32)> + Clone) -> Self {
+        let mut count = 0.0f64;
+        let mut sum = [0.0f64; 3];
+        let mut squares = [0.0f64; 3];
+        // the structure tensor of the lightness gradients
+        let (mut xx, mut yy, mut xy) = (0.0f64, 0.0f64, 0.0f64);
+        for (x, y) in points.clone() {
+            let pixel = img.get_pixel(x, y);
+            for c in 0..3 {
+                sum[c] += pixel[c] as f64;
+                squares[c] += pixel[c] as f64 * pixel[c] as f64;
+            }
+            let (gx, gy) = gradient(img, x, y);
+            xx += gx * gx;
+            yy += gy * gy;
+            xy += gx * gy;
+            count += 1.0;
+        }
+        let count = count.max(1.0);
+        let mut mean = [0u8; 3];
+        let mut variance = 0.0;
+        for c in 0..3 {
+            let channel_mean = sum[c] / count;
+            mean[c] = channel_mean.round() as u8;
+            variance += (squares[c] / count - channel_mean * channel_mean).max(0.0) / 3.0;
+        }
+
+        let mean_lightness = (sum[0] * 0.299 + sum[1] * 0.587 + sum[2] * 0.114) / count;
+        let mut histogram = [0.0f32; HISTOGRAM_BINS];
+        for (x, y) in points {
+            let offset = (lightness(img, x, y) - mean_lightness) as f32 / BIN_WIDTH + HISTOGRAM_BINS as f32 / 2.0;
+            histogram[(offset.max(0.0) as usize).min(HISTOGRAM_BINS - 1)] += 1.0;
+        }
+        for bin in histogram.iter_mut() {
+            *bin = round(*bin / count as f32);
+        }
+
+        let energy = xx + yy;
+        let (orientation, coherence) = if energy > 0.0 {
+            let orientation = (0.5 * (2.0 * xy).atan2(xx - yy)).rem_euclid(PI as f64);
+            let coherence = ((xx - yy).powi(2) + 4.0 * xy * xy).sqrt() / energy;
+            (orientation as f32, coherence as f32)
+        } else {
+            (0.0, 0.0)
+        };
+        Self { mean, variance: round(variance as f32), orientation: round(orientation), coherence: round(coherence), histogram }
+    }
+
+    // How different two textures look, apart from their color, in about the units of an
+    // RGB channel: the difference in standard deviation, plus the difference in direction when
+    // both have one, plus the difference of the histograms.
+    pub fn distance(&self, other: &TextureDescriptor) -> f32 {
+        let busyness = (self.variance.sqrt() - other.variance.sqrt()).abs();
+        let angle = (self.orientation - other.orientation).abs();
+        let orientation = angle.min(PI - angle) / FRAC_PI_2 * self.coherence.min(other.coherence);
+        let histogram: f32 = self.histogram.iter().zip(&other.histogram).map(|(a, b)| (a - b).abs()).sum();
+        busyness + orientation * ORIENTATION_SCALE + histogram * HISTOGRAM_SCALE
+    }
+}
+
+fn lightness(img: &RgbImage, x: u32, y: u32) -> f64 {
+    let pixel = img.get_pixel(x, y);
+    pixel[0] as f64 * 0.299 + pixel[1] as f64 * 0.587 + pixel[2] as f64 * 0.114
+}
+
+// central differences of the lightness, one sided at the borders
+fn gradient(img: &RgbImage, x: u32, y: u32) -> (f64, f64) {
+    let (left, right) = (x.saturating_sub(1), (x + 1).min(img.width() - 1));
+    let (top, bottom) = (y.saturating_sub(1), (y + 1).min(img.height() - 1));
+    let gx = (lightness(img, right, y) - lightness(img, left, y)) / (right - left).max(1) as f64;
+    let gy = (lightness(img, x, bottom) - lightness(img, x, top)) / (bottom - top).max(1) as f64;
+    (gx, gy)
+}
+
+// three decimals are plenty, and keep the manifest short
+fn round(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgb;
+
+    use super::*;
+
+    fn stripes(vertical: bool) -> RgbImage {
+        RgbImage::from_fn(32, 32, |x, y| {
+            let along = if vertical { x } else { y };
+            if (along / 2).is_multiple_of(2) { Rgb([40, 40, 40]) } else { Rgb([200, 200, 200]) }
+        })
+    }
+
+    #[test]
+    fn describes_smooth_and_striped_textures() {
+        let flat = TextureDescriptor::of_image(&RgbImage::from_pixel(16, 16, Rgb([120, 120, 120])));
+        assert_eq!((0.0, 0.0), (flat.variance, flat.coherence));
+        assert_eq!(1.0, flat.histogram[HISTOGRAM_BINS / 2]);
+
+        let vertical = TextureDescriptor::of_image(&stripes(true));
+        let horizontal = TextureDescriptor::of_image(&stripes(false));
+        assert_eq!(6400.0, vertical.variance);
+        assert_eq!(1.0, vertical.coherence);
+        assert_eq!(0.0, vertical.orientation);
+        assert!((horizontal.orientation - FRAC_PI_2).abs() < 0.01);
+
+        assert_eq!(0.0, vertical.distance(&vertical));
+        assert!(vertical.distance(&horizontal) > 30.0);
+        assert!(flat.distance(&vertical) > vertical.distance(&horizontal));
+    }
+
+    #[test]
+    fn describes_regions() {
+        // the left half is flat, the right half striped
+        let img = RgbImage::from_fn(32, 32, |x, y| if x < 16 { Rgb([90, 90, 90]) } else { *stripes(false).get_pixel(x, y) });
+        let left = TextureDescriptor::of_points(&img, (0..32).flat_map(|y| (0..15).map(move |x| (x, y))));
+        assert_eq!(([90, 90, 90], 0.0), (left.mean, left.variance));
+        let right = TextureDescriptor::of_points(&img, (0..32).flat_map(|y| (17..32).map(move |x| (x, y))));
+        assert_eq!(TextureDescriptor::of_image(&stripes(false)).variance, right.variance);
+    }
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SpiegelError {
+    // no filter is registered under this name
+    UnknownFilter(String),
+    // the parameters for the named filter are not valid
+    InvalidParameters(String, String),
+    // a sample that is needed is not in the cache
+    MissingSample(String),
+    // the named sample could not be loaded
+    SampleLoad(String, Box<dyn Error>),
+    // the sample manifest has a version this crate cannot read
+    UnsupportedManifest(u32),
+    // the named sample is larger than an atlas page
+    AtlasOverflow(String),
+    // no sample pack has this name
+    UnknownPack(String),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for SpiegelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpiegelError::UnknownFilter(name) => write!(f, "unknown filter {}", name),
+            SpiegelError::InvalidParameters(name, message) => write!(f, "invalid parameters for {}: {}", name, message),
+            SpiegelError::MissingSample(name) => write!(f, "sample {} is not loaded", name),
+            SpiegelError::SampleLoad(name, error) => write!(f, "cannot load sample {}: {}", name, error),
+            SpiegelError::UnsupportedManifest(version) => write!(f, "unsupported manifest version {}", version),
+            SpiegelError::AtlasOverflow(name) => write!(f, "sample {} does not fit on an atlas page", name),
+            SpiegelError::UnknownPack(name) => write!(f, "unknown sample pack {}", name),
+            SpiegelError::Io(error) => write!(f, "{}", error),
+            SpiegelError::Json(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for SpiegelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SpiegelError::SampleLoad(_, error) => Some(error.as_ref()),
+            SpiegelError::Io(error) => Some(error),
+            SpiegelError::Json(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SpiegelError {
+    fn from(error: std::io::Error) -> Self {
+        SpiegelError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SpiegelError {
+    fn from(error: serde_json::Error) -> Self {
+        SpiegelError::Json(error)
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use image::RgbImage;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::catalog::Catalog;
+use crate::color::DistanceMetric;
+use crate::error::SpiegelError;
+use crate::regions::{Connectivity, MergeBy, Regions};
+use crate::samples::ColorSample;
+use crate::tags::TagRule;
+use crate::transform::Placement;
+use crate::{quantizer, transform};
+
+// A step in a pipeline. Besides the built-in filters below, any implementation can be
+// registered in a FilterRegistry and used by name in a pipeline.
+pub trait Filter {
+    fn apply(&self, img: &RgbImage, ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError>;
+}
+
+// state shared by all filters during one run of a pipeline
+pub struct FilterContext {
+    // the image the pipeline started with
+    pub original: RgbImage,
+    // the samples to choose from
+    pub catalog: Arc<Catalog>,
+    // the loaded samples of the catalog that this run can use, by id
+    pub samples: HashMap<String, Arc<ColorSample>>,
+    // the regions that were asked for last
+    regions: Option<RegionCache>,
+    // the samples that were chosen last
+    chosen: Option<ChoiceCache>,
+}
+
+struct RegionCache {
+    image: RgbImage,
+    metric: DistanceMetric,
+    tolerance: f32,
+    connectivity: Connectivity,
+    regions: Arc<Regions>,
+}
+
+struct ChoiceCache {
+    regions: Arc<Regions>,
+    fill: SampleFill,
+    chosen: Arc<Vec<Option<usize>>>,
+}
+
+impl FilterContext {
+    pub fn new(original: &RgbImage, catalog: Arc<Catalog>) -> Self {
+        Self { original: original.clone(), catalog, samples: HashMap::new(), regions: None, chosen: None }
+    }
+
+    // The regions of img with their graph, for filters that look at the neighbours of a region.
+    // They are only labeled again when the image or the parameters change, so that the
+    // sample fill and the stages around it can share them.
+    pub fn regions(&mut self, img: &RgbImage, metric: DistanceMetric, tolerance: f32, connectivity: Connectivity) -> Arc<Regions> {
+        if let Some(cache) = &self.regions {
+            if (cache.metric, cache.tolerance, cache.connectivity) == (metric, tolerance, connectivity) && cache.image == *img {
+                return cache.regions.clone();
+            }
+        }
+        let regions = Arc::new(Regions::new(img, metric, tolerance, connectivity));
+        self.regions = Some(RegionCache { image: img.clone(), metric, tolerance, connectivity, regions: regions.clone() });
+        regions
+    }
+
+    // The position in the catalog of the sample of every region of img for fill, by label.
+    // They are only chosen again when the regions or fill change, so that the sample fill
+    // uses the choice that its samples were loaded for.
+    pub fn chosen_samples(&mut self, img: &RgbImage, fill: &SampleFill) -> Arc<Vec<Option<usize>>> {
+        let regions = self.regions(img, fill.metric, fill.tolerance, fill.connectivity);
+        if let Some(cache) = &self.chosen {
+            if Arc::ptr_eq(&cache.regions, &regions) && cache.fill == *fill {
+                return cache.chosen.clone();
+            }
+        }
+        let chosen = Arc::new(transform::choose(img, &regions, &self.original, &self.catalog, fill));
+        self.chosen = Some(ChoiceCache { regions, fill: fill.clone(), chosen: chosen.clone() });
+        chosen
+    }
+
+    pub fn add_sample(&mut self, sample: Arc<ColorSample>) {
+        self.samples.insert(sample.id.clone(), sample);
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GaussianBlur {
+    pub sigma: f32,
+}
+
+impl Filter for GaussianBlur {
+    fn apply(&self, img: &RgbImage, _ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError> {
+        // imageproc panics for these
+        if !self.sigma.is_finite() || self.sigma <= 0.0 {
+            return Err(SpiegelError::InvalidParameters("gaussian_blur".to_owned(), format!("sigma must be positive, not {}", self.sigma)));
+        }
+        Ok(imageproc::filter::gaussian_blur_f32(img, self.sigma))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Median {
+    pub x_radius: u32,
+    pub y_radius: u32,
+}
+
+impl Filter for Median {
+    fn apply(&self, img: &RgbImage, _ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError> {
+        Ok(imageproc::filter::median_filter(img, self.x_radius, self.y_radius))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Quantize {
+    pub colors: usize,
+}
+
+impl Filter for Quantize {
+    fn apply(&self, img: &RgbImage, _ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError> {
+        Ok(quantizer::quantize(img, self.colors))
+    }
+}
+
+// Merges the regions smaller than min_area into a neighbour, so that the speckles the
+// quantization leaves do not each get their own sample. The regions are those of a sample
+// fill with the same metric, tolerance and connectivity, and the pixels of a merged region
+// get the color of the neighbour.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeRegions {
+    #[serde(default)]
+    pub metric: DistanceMetric,
+    pub tolerance: f32,
+    #[serde(default)]
+    pub connectivity: Connectivity,
+    pub min_area: u32,
+    #[serde(default)]
+    pub by: MergeBy,
+}
+
+impl Default for MergeRegions {
+    // the regions of a default sample fill, merged by color below 16 pixels
+    fn default() -> Self {
+        Self {
+            metric: DistanceMetric::Rgb,
+            tolerance: 4.0,
+            connectivity: Connectivity::Four,
+            min_area: 16,
+            by: MergeBy::Color,
+        }
+    }
+}
+
+impl Filter for MergeRegions {
+    fn apply(&self, img: &RgbImage, ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError> {
+        let regions = ctx.regions(img, self.metric, self.tolerance, self.connectivity);
+        let map = &regions.map;
+        let merged = map.merged(self.min_area, self.by, self.metric);
+        let mut out = img.clone();
+        for (x, y, pixel) in out.enumerate_pixels_mut() {
+            let region = &merged.regions()[merged.label(x, y)];
+            if map.regions()[map.label(x, y)].first != region.first {
+                *pixel = region.color;
+            }
+        }
+        Ok(out)
+    }
+}
+
+// fills every region with the closest sample, which must be in the context already
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampleFill {
+    #[serde(default)]
+    pub metric: DistanceMetric,
+    pub tolerance: f32,
+    // whether pixels that only share a corner belong to the same region
+    #[serde(default)]
+    pub connectivity: Connectivity,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<TagRule>,
+    // how much the texture counts next to the color, 0 for matching on color alone
+    #[serde(default)]
+    pub texture: f32,
+    // Each region gets one of the closest choices samples, at random but weighted by how close
+    // they are, and at most spread further away than the closest. The same seed gives the same
+    // choices. With 0 or 1 choices, every region gets the closest sample.
+    #[serde(default)]
+    pub choices: usize,
+    #[serde(default = "default_spread")]
+    pub spread: f32,
+    #[serde(default)]
+    pub seed: u64,
+    // whether touching regions get different samples, where the closest samples allow it
+    #[serde(default)]
+    pub distinct: bool,
+    #[serde(default)]
+    pub placement: Placement,
+}
+
+pub fn default_spread() -> f32 {
+    8.0
+}
+
+impl Default for SampleFill {
+    // the closest sample by color for every region, as in the original spiegel filter
+    fn default() -> Self {
+        Self {
+            metric: DistanceMetric::Rgb,
+            tolerance: 4.0,
+            connectivity: Connectivity::Four,
+            tags: vec![],
+            texture: 0.0,
+            choices: 1,
+            spread: default_spread(),
+            seed: 0,
+            distinct: false,
+            placement: Placement::default(),
+        }
+    }
+}
+
+impl Filter for SampleFill {
+    fn apply(&self, img: &RgbImage, ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError> {
+        let chosen = ctx.chosen_samples(img, self);
+        let regions = ctx.regions(img, self.metric, self.tolerance, self.connectivity);
+        let required = transform::required_samples(&ctx.catalog, &chosen);
+        if let Some(missing) = required.into_iter().find(|entry| !ctx.samples.contains_key(&entry.id)) {
+            return Err(SpiegelError::MissingSample(missing.id.clone()));
+        }
+        Ok(transform::apply_samples_to_image(img.clone(), &regions, &chosen, &ctx.catalog, &ctx.samples, self))
+    }
+}
+
+// creates a filter from the parameters of a pipeline stage
+pub type FilterFactory = Box<dyn Fn(&Value) -> Result<Box<dyn Filter>, SpiegelError>>;
+
+// Filters by name, so that pipelines can use filters that are not part of this crate.
+// The built-in filters are registered under the names of their pipeline stages, except for
+// the sample fill and the region merge: a pipeline only gathers samples for its sample_fill
+// stages, so they cannot run as custom filters.
+pub struct FilterRegistry {
+    factories: HashMap<String, FilterFactory>,
+}
+
+impl Default for FilterRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register_params::<GaussianBlur>("gaussian_blur");
+        registry.register_params::<Median>("median");
+        registry.register_params::<Quantize>("quantize");
+        registry
+    }
+}
+
+impl FilterRegistry {
+    // an empty registry, without the built-in filters
+    pub fn new() -> Self {
+        Self { factories: HashMap::new() }
+    }
+
+    // registers (or replaces) the factory for name
+    pub fn register<F>(&mut self, name: &str, factory: F)
+        where
+            F: Fn(&Value) -> Result<Box<dyn Filter>, SpiegelError> + 'static,
+    {
+        self.factories.insert(name.to_owned(), Box::new(factory));
+    }
+
+    // registers a filter that is deserialized from the stage parameters
+    pub fn register_params<T>(&mut self, name: &str)
+        where
+            T: Filter + DeserializeOwned + 'static,
+    {
+        let filter_name = name.to_owned();
+        self.register(name, move |params| {
+            let filter: T = serde_json::from_value(params.clone())
+                .map_err(|e| SpiegelError::InvalidParameters(filter_name.clone(), e.to_string()))?;
+            Ok(Box::new(filter))
+        });
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    pub fn create(&self, name: &str, params: &Value) -> Result<Box<dyn Filter>, SpiegelError> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| SpiegelError::UnknownFilter(name.to_owned()))?;
+        factory(params)
+    }
+}
@@ -0,0 +1,212 @@
+use std::cmp::Ordering;
+
+use image::Rgb;
+
+use crate::color::{self, DistanceMetric};
+
+// Ciede2000 is not a euclidean distance, so for it the tree is searched in Lab with
+// the Cie76 distance, and the candidates are ranked with Ciede2000 afterwards.
+// Within the sRGB gamut Cie76 is at most about 7.4 times Ciede2000 (for saturated blues),
+// so searching Cie76 up to this factor times a Ciede2000 distance finds every color within it.
+const CIEDE2000_BOUND: f32 = 8.0;
+
+// A k-d tree over the colors of a sample palette, for nearest neighbour lookups with any
+// of the distance metrics. Results are indexes into the colors the index was built from,
+// paired with their distance, closest first.
+pub struct SampleIndex {
+    metric: DistanceMetric,
+    colors: Vec<Rgb<u8>>,
+    // coordinates of the colors in the space of the metric
+    points: Vec<[f32; 3]>,
+    // the tree, as a permutation of the points: each range is split by the median on axis depth % 3
+    tree: Vec<usize>,
+}
+
+impl SampleIndex {
+    pub fn new(colors: &[Rgb<u8>], metric: DistanceMetric) -> Self {
+        let points: Vec<[f32; 3]> = colors.iter().map(|c| metric.coordinates(c)).collect();
+        let mut tree: Vec<usize> = (0..points.len()).collect();
+        build(&points, &mut tree, 0);
+        Self { metric, colors: colors.to_vec(), points, tree }
+    }
+
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn nearest(&self, color: &Rgb<u8>) -> Option<(usize, f32)> {
+        self.k_nearest(color, 1).into_iter().next()
+    }
+
+    pub fn k_nearest(&self, color: &Rgb<u8>, k: usize) -> Vec<(usize, f32)> {
+        if k == 0 {
+            return vec![];
+        }
+        let query = self.metric.coordinates(color);
+        let mut found = Vec::with_capacity(k + 1);
+        self.search_nearest(&query, k, 0, self.tree.len(), 0, &mut found);
+        if self.metric != DistanceMetric::Ciede2000 {
+            return found;
+        }
+        // the k closest in Lab give an upper limit for the k closest in Ciede2000
+        match self.rerank(color, found, k).last() {
+            Some((_, limit)) => {
+                let mut found = self.within(color, *limit);
+                found.truncate(k);
+                found
+            }
+            None => vec![],
+        }
+    }
+
+    // all colors within radius, closest first
+    pub fn within(&self, color: &Rgb<u8>, radius: f32) -> Vec<(usize, f32)> {
+        let query = self.metric.coordinates(color);
+        let search_radius = if self.metric == DistanceMetric::Ciede2000 { radius * CIEDE2000_BOUND } else { radius };
+        let mut found = vec![];
+        self.search_radius(&query, search_radius, 0, self.tree.len(), 0, &mut found);
+        let mut found = self.rerank(color, found, usize::MAX);
+        found.retain(|(_, distance)| *distance <= radius);
+        found
+    }
+
+    // replaces the tree distances by the distances of the metric, when they differ
+    fn rerank(&self, color: &Rgb<u8>, mut found: Vec<(usize, f32)>, k: usize) -> Vec<(usize, f32)> {
+        if self.metric == DistanceMetric::Ciede2000 {
+            let lab = color::to_lab(color);
+            for (index, distance) in found.iter_mut() {
+                *distance = color::ciede2000(lab, self.points[*index]);
+            }
+        }
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        found.truncate(k);
+        found
+    }
+
+    fn search_nearest(&self, query: &[f32; 3], k: usize, lo: usize, hi: usize, depth: usize, found: &mut Vec<(usize, f32)>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.tree[mid];
+        let point = &self.points[index];
+        let distance = color::euclidean(*query, *point);
+        if found.len() < k || distance < found[found.len() - 1].1 {
+            let position = found.partition_point(|(_, d)| *d <= distance);
+            found.insert(position, (index, distance));
+            found.truncate(k);
+        }
+
+        let axis = depth % 3;
+        let diff = query[axis] - point[axis];
+        let (near, far) = if diff < 0.0 { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+        self.search_nearest(query, k, near.0, near.1, depth + 1, found);
+        if found.len() < k || diff.abs() < found[found.len() - 1].1 {
+            self.search_nearest(query, k, far.0, far.1, depth + 1, found);
+        }
+    }
+
+    fn search_radius(&self, query: &[f32; 3], radius: f32, lo: usize, hi: usize, depth: usize, found: &mut Vec<(usize, f32)>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.tree[mid];
+        let point = &self.points[index];
+        let distance = color::euclidean(*query, *point);
+        if distance <= radius {
+            found.push((index, distance));
+        }
+
+        let axis = depth % 3;
+        let diff = query[axis] - point[axis];
+        if diff - radius <= 0.0 {
+            self.search_radius(query, radius, lo, mid, depth + 1, found);
+        }
+        if diff + radius >= 0.0 {
+            self.search_radius(query, radius, mid + 1, hi, depth + 1, found);
+        }
+    }
+
+    pub fn color(&self, index: usize) -> &Rgb<u8> {
+        &self.colors[index]
+    }
+}
+
+// puts the median of the range on axis depth % 3 in the middle, and recurses on both halves
+fn build(points: &[[f32; 3]], range: &mut [usize], depth: usize) {
+    if range.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = range.len() / 2;
+    range.select_nth_unstable_by(mid, |a, b| {
+        points[*a][axis].partial_cmp(&points[*b][axis]).unwrap_or(Ordering::Equal)
+    });
+    let (left, right) = range.split_at_mut(mid);
+    build(points, left, depth + 1);
+    build(points, &mut right[1..], depth + 1);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const METRICS: [DistanceMetric; 5] = [
+        DistanceMetric::Rgb,
+        DistanceMetric::WeightedRgb,
+        DistanceMetric::Cie76,
+        DistanceMetric::Ciede2000,
+        DistanceMetric::Oklab,
+    ];
+
+    fn random_colors(count: usize, seed: u32) -> Vec<Rgb<u8>> {
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        };
+        (0..count).map(|_| Rgb([next(), next(), next()])).collect()
+    }
+
+    fn brute_force(colors: &[Rgb<u8>], color: &Rgb<u8>, metric: DistanceMetric) -> Vec<(usize, f32)> {
+        let mut all: Vec<(usize, f32)> = colors.iter().enumerate().map(|(i, c)| (i, metric.distance(color, c))).collect();
+        all.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        all
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let palette = random_colors(500, 1);
+        for metric in METRICS {
+            let index = SampleIndex::new(&palette, metric);
+            for color in random_colors(100, 2) {
+                let expected = brute_force(&palette, &color, metric);
+                let nearest = index.nearest(&color).unwrap();
+                assert_eq!(expected[0].1, nearest.1, "{:?} {:?}", metric, color);
+
+                let k_nearest: Vec<f32> = index.k_nearest(&color, 5).iter().map(|(_, d)| *d).collect();
+                let expected_k: Vec<f32> = expected[..5].iter().map(|(_, d)| *d).collect();
+                assert_eq!(expected_k, k_nearest, "{:?} {:?}", metric, color);
+
+                let radius = expected[10].1;
+                assert_eq!(expected.iter().filter(|(_, d)| *d <= radius).count(), index.within(&color, radius).len());
+            }
+        }
+    }
+
+    #[test]
+    fn empty_index() {
+        let index = SampleIndex::new(&[], DistanceMetric::Rgb);
+        assert!(index.nearest(&Rgb([1, 2, 3])).is_none());
+        assert!(index.within(&Rgb([1, 2, 3]), 100.0).is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+pub mod atlas;
+pub mod catalog;
+pub mod color;
+pub mod descriptor;
+pub mod error;
+pub mod filter;
+pub mod index;
+pub mod library;
+pub mod pack;
+pub mod pipeline;
+pub mod provider;
+pub mod quantizer;
+pub mod regions;
+pub mod samples;
+pub mod tags;
+pub mod transform;
@@ -0,0 +1,337 @@
+use std::cmp::Ordering;
+
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+
+use crate::catalog::{Catalog, SampleEntry};
+use crate::color::{self, DistanceMetric};
+use crate::descriptor::TextureDescriptor;
+use crate::tags;
+
+// the color distance for checking the catalog, in ΔE
+const CHECK_METRIC: DistanceMetric = DistanceMetric::Ciede2000;
+
+// how the sample library builder cuts patches out of a photo
+#[derive(Debug, Clone)]
+pub struct PatchOptions {
+    // side of the squares, in pixels of the photo
+    pub size: u32,
+    // patches with a higher color variance are not uniform enough to be a sample
+    pub max_variance: f32,
+    // the most uniform patches are taken first, up to this many
+    pub max_count: usize,
+}
+
+impl Default for PatchOptions {
+    fn default() -> Self {
+        Self { size: 512, max_variance: 400.0, max_count: 4 }
+    }
+}
+
+// a square of a photo, with its position in the photo
+pub struct Patch {
+    pub x: u32,
+    pub y: u32,
+    pub image: RgbImage,
+    pub measurement: Measurement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub mean: [u8; 3],
+    // the variance of the channels around the mean, averaged over the channels
+    pub variance: f32,
+}
+
+pub fn measure(img: &RgbImage) -> Measurement {
+    let count = (img.width() as f64 * img.height() as f64).max(1.0);
+    let mut sum = [0.0f64; 3];
+    let mut squares = [0.0f64; 3];
+    for pixel in img.pixels() {
+        for c in 0..3 {
+            let value = pixel[c] as f64;
+            sum[c] += value;
+            squares[c] += value * value;
+        }
+    }
+    let mut mean = [0u8; 3];
+    let mut variance = 0.0;
+    for c in 0..3 {
+        let channel_mean = sum[c] / count;
+        mean[c] = channel_mean.round() as u8;
+        variance += (squares[c] / count - channel_mean * channel_mean).max(0.0) / 3.0;
+    }
+    Measurement { mean, variance: variance as f32 }
+}
+
+// Finds the most uniform squares of the photo. Candidates overlap by half their size,
+// the chosen patches never overlap each other.
+pub fn extract_patches(photo: &RgbImage, options: &PatchOptions) -> Vec<Patch> {
+    let size = options.size;
+    if size == 0 || photo.width() < size || photo.height() < size {
+        return vec![];
+    }
+    let step = (size / 2).max(1);
+    let mut candidates = vec![];
+    for y in (0..=photo.height() - size).step_by(step as usize) {
+        for x in (0..=photo.width() - size).step_by(step as usize) {
+            let image = imageops::crop_imm(photo, x, y, size, size).to_image();
+            let measurement = measure(&image);
+            if measurement.variance <= options.max_variance {
+                candidates.push(Patch { x, y, image, measurement });
+            }
+        }
+    }
+    candidates.sort_by(|a, b| a.measurement.variance.partial_cmp(&b.measurement.variance).unwrap_or(Ordering::Equal));
+
+    let mut patches: Vec<Patch> = vec![];
+    for candidate in candidates {
+        if patches.len() >= options.max_count {
+            break;
+        }
+        let overlaps = patches.iter().any(|p| p.x.abs_diff(candidate.x) < size && p.y.abs_diff(candidate.y) < size);
+        if !overlaps {
+            patches.push(candidate);
+        }
+    }
+    patches
+}
+
+// scales a patch to the size of the samples in the library
+pub fn normalize(patch: &RgbImage, size: u32) -> RgbImage {
+    if patch.dimensions() == (size, size) {
+        return patch.clone();
+    }
+    imageops::resize(patch, size, size, FilterType::Lanczos3)
+}
+
+// scales an image down, keeping its aspect, so that its longest side is at most max_side
+pub fn shrink(img: &RgbImage, max_side: u32) -> RgbImage {
+    let longest = img.width().max(img.height());
+    if longest <= max_side {
+        return img.clone();
+    }
+    let scale = |side: u32| ((side as u64 * max_side as u64 / longest as u64) as u32).max(1);
+    imageops::resize(img, scale(img.width()), scale(img.height()), FilterType::Triangle)
+}
+
+// A sample that tiles without seams, by offset and blend: every pixel is a mix of the image
+// and its copies shifted by half its width, half its height and both. Near the middle the
+// image itself counts most, near the edges the shifted copies, whose own edges meet in the
+// middle, so the left edge continues the right one and the top the bottom. Where the copies
+// mix evenly, the texture loses some of its contrast.
+pub fn seamless(img: &RgbImage) -> RgbImage {
+    let (width, height) = img.dimensions();
+    // 1 in the middle, down to 0 at the edges
+    let weight = |i: u32, side: u32| 1.0 - ((2 * i + 1) as f32 / side as f32 - 1.0).abs();
+    RgbImage::from_fn(width, height, |x, y| {
+        let (wx, wy) = (weight(x, width), weight(y, height));
+        let (sx, sy) = ((x + width / 2) % width, (y + height / 2) % height);
+        let copies = [
+            (img.get_pixel(x, y), wx * wy),
+            (img.get_pixel(sx, y), (1.0 - wx) * wy),
+            (img.get_pixel(x, sy), wx * (1.0 - wy)),
+            (img.get_pixel(sx, sy), (1.0 - wx) * (1.0 - wy)),
+        ];
+        let mut pixel = [0.0f32; 3];
+        for (copy, weight) in copies {
+            for c in 0..3 {
+                pixel[c] += copy[c] as f32 * weight;
+            }
+        }
+        Rgb(pixel.map(|c| c.round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+// The brightness and pattern scale tags of a sample. The scale is how much of the variance
+// is left when the sample is scaled down 8 times: little for fine patterns, most of it for
+// coarse ones.
+pub fn describe(img: &RgbImage, measurement: &Measurement) -> Vec<String> {
+    let lightness = color::to_lab(&Rgb(measurement.mean))[0];
+    let brightness = match lightness {
+        l if l < 35.0 => "dark",
+        l if l > 70.0 => "light",
+        _ => "mid",
+    };
+    let scale = if measurement.variance < 1.0 {
+        "flat"
+    } else {
+        let small = imageops::resize(img, (img.width() / 8).max(1), (img.height() / 8).max(1), FilterType::Triangle);
+        match measure(&small).variance / measurement.variance {
+            kept if kept < 0.3 => "fine",
+            kept if kept < 0.7 => "medium",
+            _ => "coarse",
+        }
+    };
+    vec![format!("{}:{}", tags::BRIGHTNESS, brightness), format!("{}:{}", tags::SCALE, scale)]
+}
+
+// adds the tags, replacing the tags with the same key
+pub fn set_tags(tags: &mut Vec<String>, new: &[String]) {
+    tags.retain(|tag| tags::key(tag).is_none_or(|key| new.iter().all(|other| tags::key(other) != Some(key))));
+    tags.extend(new.iter().filter(|tag| tags::key(tag).is_some()).cloned());
+    for tag in new.iter().filter(|tag| tags::key(tag).is_none()) {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+}
+
+// samples are named after their mean color, with a suffix when the library already has one
+pub fn sample_id(catalog: &Catalog, mean: [u8; 3]) -> String {
+    let id = format!("{:02x}{:02x}{:02x}", mean[0], mean[1], mean[2]);
+    if catalog.get(&id).is_none() {
+        return id;
+    }
+    (2..)
+        .map(|n| format!("{}-{}", id, n))
+        .find(|candidate| catalog.get(candidate).is_none())
+        .unwrap()
+}
+
+// the catalog entry for a normalized sample, stored as <id>.jpg, with the tags and the
+// ones that describe it
+pub fn entry(catalog: &Catalog, image: &RgbImage, tags: &[String]) -> SampleEntry {
+    let measurement = measure(image);
+    let id = sample_id(catalog, measurement.mean);
+    let mut entry = SampleEntry {
+        variance: Some(measurement.variance),
+        tags: tags.to_vec(),
+        descriptor: Some(TextureDescriptor::of_image(image)),
+        ..SampleEntry::new(&id, measurement.mean, [image.width(), image.height()])
+    };
+    set_tags(&mut entry.tags, &describe(image, &measurement));
+    entry
+}
+
+// ΔE between a declared color of a sample and its measured mean
+pub fn drift(declared: &Rgb<u8>, measurement: &Measurement) -> f32 {
+    CHECK_METRIC.distance(declared, &Rgb(measurement.mean))
+}
+
+// the color in ids like 2a3960, the naming of the original library
+pub fn id_color(id: &str) -> Option<Rgb<u8>> {
+    let hex = id.get(..6)?;
+    if !(id.len() == 6 || id[6..].starts_with('-')) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(Rgb([channel(0)?, channel(2)?, channel(4)?]))
+}
+
+// pairs of samples, as positions in the catalog, whose colors are at most max_distance ΔE apart
+pub fn near_duplicates(catalog: &Catalog, max_distance: f32) -> Vec<(usize, usize, f32)> {
+    let index = catalog.index(CHECK_METRIC);
+    let mut pairs = vec![];
+    for (i, entry) in catalog.samples.iter().enumerate() {
+        for (j, distance) in index.within(&entry.color(), max_distance) {
+            if i < j {
+                pairs.push((i, j, distance));
+            }
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn measures_mean_and_variance() {
+        let uniform = RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]));
+        assert_eq!(Measurement { mean: [10, 20, 30], variance: 0.0 }, measure(&uniform));
+
+        let checkered = RgbImage::from_fn(4, 4, |x, y| if (x + y) % 2 == 0 { Rgb([0, 0, 0]) } else { Rgb([20, 20, 20]) });
+        assert_eq!(Measurement { mean: [10, 10, 10], variance: 100.0 }, measure(&checkered));
+    }
+
+    #[test]
+    fn extracts_uniform_patches() {
+        // noise on the left half, a flat color on the right half
+        let photo = RgbImage::from_fn(64, 32, |x, y| {
+            if x < 32 {
+                let noise = ((x * 7919 + y * 104729) % 256) as u8;
+                Rgb([noise, 255 - noise, noise / 2])
+            } else {
+                Rgb([200, 100, 50])
+            }
+        });
+        let options = PatchOptions { size: 16, max_variance: 10.0, max_count: 10 };
+        let patches = extract_patches(&photo, &options);
+
+        assert_eq!(4, patches.len());
+        for patch in &patches {
+            assert!(patch.x >= 32);
+            assert_eq!([200, 100, 50], patch.measurement.mean);
+        }
+    }
+
+    #[test]
+    fn shrinks_to_the_longest_side() {
+        let img = RgbImage::new(400, 100);
+        assert_eq!((200, 50), shrink(&img, 200).dimensions());
+        assert_eq!((400, 100), shrink(&img, 500).dimensions());
+    }
+
+    #[test]
+    fn describes_brightness_and_scale() {
+        let stripes = |width: u32| RgbImage::from_fn(256, 256, |x, _| if (x / width).is_multiple_of(2) { Rgb([20, 20, 20]) } else { Rgb([60, 60, 60]) });
+        let fine = stripes(1);
+        assert_eq!(vec!["brightness:dark", "scale:fine"], describe(&fine, &measure(&fine)));
+        let coarse = stripes(64);
+        assert_eq!(vec!["brightness:dark", "scale:coarse"], describe(&coarse, &measure(&coarse)));
+        let white = RgbImage::from_pixel(8, 8, Rgb([250, 250, 250]));
+        assert_eq!(vec!["brightness:light", "scale:flat"], describe(&white, &measure(&white)));
+
+        let mut tags = vec!["wood".to_owned(), "scale:fine".to_owned()];
+        set_tags(&mut tags, &["scale:coarse".to_owned(), "wood".to_owned(), "source:photo".to_owned()]);
+        assert_eq!(vec!["wood", "scale:coarse", "source:photo"], tags);
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        let mut catalog = Catalog::default();
+        let image = RgbImage::from_pixel(8, 8, Rgb([0x12, 0x11, 0x0f]));
+        for expected in ["12110f", "12110f-2", "12110f-3"] {
+            let entry = entry(&catalog, &image, &[]);
+            assert_eq!(expected, entry.id);
+            catalog.samples.push(entry);
+        }
+    }
+
+    #[test]
+    fn checks_catalog_colors() {
+        assert_eq!(Some(Rgb([0x2a, 0x39, 0x60])), id_color("2a3960"));
+        assert_eq!(Some(Rgb([0x2a, 0x39, 0x60])), id_color("2a3960-2"));
+        assert_eq!(None, id_color("moss"));
+        assert_eq!(None, id_color("2a39601"));
+
+        let sample = |id, mean| SampleEntry::new(id, mean, [8, 8]);
+        let catalog = Catalog {
+            samples: vec![sample("2a3960", [0x2a, 0x39, 0x60]), sample("808080", [0x80, 0x80, 0x80]), sample("2a3961", [0x2a, 0x39, 0x61])],
+            ..Catalog::default()
+        };
+        let pairs: Vec<(usize, usize)> = near_duplicates(&catalog, 1.0).into_iter().map(|(i, j, _)| (i, j)).collect();
+        assert_eq!(vec![(0, 2)], pairs);
+
+        let measured = measure(&RgbImage::from_pixel(8, 8, Rgb([0x80, 0x80, 0x80])));
+        assert_eq!(0.0, drift(&catalog.samples[1].color(), &measured));
+        assert!(drift(&catalog.samples[0].color(), &measured) > 10.0);
+    }
+
+    #[test]
+    fn seamless_samples_wrap_around() {
+        let uniform = RgbImage::from_pixel(8, 8, Rgb([10, 20, 30]));
+        assert_eq!(uniform, seamless(&uniform));
+
+        // a gradient jumps from 240 back to 0 when it is tiled
+        let gradient = RgbImage::from_fn(16, 16, |x, y| Rgb([x as u8 * 16, y as u8 * 16, 0]));
+        let out = seamless(&gradient);
+        let jump = |a: &Rgb<u8>, b: &Rgb<u8>, c: usize| (a[c] as i32 - b[c] as i32).abs();
+        for i in 0..16 {
+            assert!(jump(out.get_pixel(15, i), out.get_pixel(0, i), 0) <= 16);
+            assert!(jump(out.get_pixel(i, 15), out.get_pixel(i, 0), 1) <= 16);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::{Catalog, SampleEntry};
+use crate::color::DistanceMetric;
+use crate::error::SpiegelError;
+use crate::index::SampleIndex;
+
+// the list of packs, in the directory that holds them
+pub const PACKS_FILE: &str = "packs.json";
+
+// The sample packs that are available, each one a sample library (a manifest.json with its
+// images) in its own directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PackList {
+    pub packs: Vec<PackInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackInfo {
+    pub name: String,
+    // directory of the library, relative to packs.json
+    pub dir: String,
+}
+
+impl PackList {
+    pub fn from_json(json: &str) -> Result<Self, SpiegelError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PackInfo> {
+        self.packs.iter().find(|pack| pack.name == name)
+    }
+
+    // the packs to use with their weights, all of them when nothing is selected
+    pub fn select(&self, selection: &[PackWeight]) -> Result<Vec<(PackInfo, PackWeight)>, SpiegelError> {
+        if selection.is_empty() {
+            return Ok(self.packs.iter().map(|pack| (pack.clone(), PackWeight::new(&pack.name, 1.0))).collect());
+        }
+        selection
+            .iter()
+            .map(|weight| match self.get(&weight.name) {
+                Some(pack) => Ok((pack.clone(), weight.clone())),
+                None => Err(SpiegelError::UnknownPack(weight.name.clone())),
+            })
+            .collect()
+    }
+}
+
+// An active pack. The color distances to its samples are divided by the weight, so the
+// samples of a pack with weight 2 win over those of a pack with weight 1 that are up to
+// twice as close.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackWeight {
+    pub name: String,
+    pub weight: f32,
+}
+
+impl PackWeight {
+    pub fn new(name: &str, weight: f32) -> Self {
+        Self { name: name.to_owned(), weight }
+    }
+}
+
+// name or name:weight
+impl FromStr for PackWeight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, weight) = match s.split_once(':') {
+            Some((name, weight)) => (name, weight.parse::<f32>().map_err(|e| e.to_string())?),
+            None => (s, 1.0),
+        };
+        if name.is_empty() {
+            return Err("the pack has no name".to_owned());
+        }
+        if !(weight.is_finite() && weight > 0.0) {
+            return Err(format!("the weight of {} is not positive", name));
+        }
+        Ok(Self::new(name, weight))
+    }
+}
+
+// Nearest sample lookups over the packs of a catalog: every pack has its own index, and the
+// closest sample is the one with the smallest distance divided by the weight of its pack.
+// Samples without a pack, or from a pack without a weight in the catalog, have weight 1.
+pub struct PackIndex {
+    packs: Vec<PackSamples>,
+}
+
+struct PackSamples {
+    weight: f32,
+    index: SampleIndex,
+    // the position in the catalog of every color in the index
+    positions: Vec<usize>,
+}
+
+impl PackIndex {
+    pub fn new(catalog: &Catalog, metric: DistanceMetric) -> Self {
+        Self::filtered(catalog, metric, |_| true)
+    }
+
+    // an index over the samples that are allowed
+    pub fn filtered(catalog: &Catalog, metric: DistanceMetric, allowed: impl Fn(&SampleEntry) -> bool) -> Self {
+        let mut groups: Vec<(Option<&str>, Vec<usize>)> = vec![];
+        for (i, entry) in catalog.samples.iter().enumerate().filter(|(_, entry)| allowed(entry)) {
+            let pack = entry.pack.as_deref();
+            match groups.iter_mut().find(|(name, _)| *name == pack) {
+                Some((_, positions)) => positions.push(i),
+                None => groups.push((pack, vec![i])),
+            }
+        }
+        let packs = groups
+            .into_iter()
+            .map(|(pack, positions)| {
+                let weight = pack.and_then(|name| catalog.weight(name)).unwrap_or(1.0);
+                let colors: Vec<Rgb<u8>> = positions.iter().map(|i| catalog.samples[*i].color()).collect();
+                PackSamples { weight, index: SampleIndex::new(&colors, metric), positions }
+            })
+            .collect();
+        Self { packs }
+    }
+
+    // the position in the catalog of the closest sample, with its weighted distance
+    pub fn nearest(&self, color: &Rgb<u8>) -> Option<(usize, f32)> {
+        self.packs
+            .iter()
+            .filter_map(|pack| pack.index.nearest(color).map(|(i, distance)| (pack.positions[i], distance / pack.weight)))
+            .fold(None, |closest, found| match closest {
+                Some((_, distance)) if distance <= found.1 => closest,
+                _ => Some(found),
+            })
+    }
+
+    // the k closest samples over all packs, closest first
+    pub fn k_nearest(&self, color: &Rgb<u8>, k: usize) -> Vec<(usize, f32)> {
+        let mut found: Vec<(usize, f32)> = self.packs
+            .iter()
+            .flat_map(|pack| pack.index.k_nearest(color, k).into_iter().map(|(i, distance)| (pack.positions[i], distance / pack.weight)))
+            .collect();
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+        found.truncate(k);
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(id: &str, mean: [u8; 3], pack: &str) -> SampleEntry {
+        SampleEntry { pack: Some(pack.to_owned()), ..SampleEntry::new(id, mean, [1, 1]) }
+    }
+
+    #[test]
+    fn parses_weights() {
+        assert_eq!(PackWeight::new("stone", 1.0), "stone".parse().unwrap());
+        assert_eq!(PackWeight::new("stone", 2.5), "stone:2.5".parse().unwrap());
+        assert!("stone:0".parse::<PackWeight>().is_err());
+        assert!(":2".parse::<PackWeight>().is_err());
+    }
+
+    #[test]
+    fn weights_prefer_packs() {
+        let mut catalog = Catalog::default();
+        catalog.samples.push(entry("stone/grey", [100, 100, 100], "stone"));
+        catalog.samples.push(entry("fabric/grey", [110, 100, 100], "fabric"));
+        let color = Rgb([101, 100, 100]);
+        assert_eq!(Some(0), PackIndex::new(&catalog, DistanceMetric::Rgb).nearest(&color).map(|(i, _)| i));
+
+        catalog.packs = vec![PackWeight::new("stone", 1.0), PackWeight::new("fabric", 10.0)];
+        let (i, distance) = PackIndex::new(&catalog, DistanceMetric::Rgb).nearest(&color).unwrap();
+        assert_eq!((1, 0.9), (i, distance));
+    }
+
+    #[test]
+    fn selects_known_packs() {
+        let list = PackList::from_json(r#"{"packs": [{"name": "stone", "dir": "stone"}, {"name": "fabric", "dir": "fabric"}]}"#).unwrap();
+        assert_eq!(2, list.select(&[]).unwrap().len());
+        let selected = list.select(&["fabric:3".parse().unwrap()]).unwrap();
+        assert_eq!(vec![("fabric", 3.0)], selected.iter().map(|(pack, weight)| (pack.dir.as_str(), weight.weight)).collect::<Vec<_>>());
+        assert!(matches!(list.select(&["wood".parse().unwrap()]), Err(SpiegelError::UnknownPack(name)) if name == "wood"));
+    }
+}
@@ -0,0 +1,290 @@
+use image::RgbImage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::catalog::SampleEntry;
+use crate::error::SpiegelError;
+use crate::filter::{self, Filter, FilterContext, FilterRegistry};
+use crate::provider::SampleProvider;
+use crate::samples::SampleStore;
+use crate::transform;
+
+// One step of the transformation, with its parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Stage {
+    GaussianBlur { sigma: f32 },
+    Median { x_radius: u32, y_radius: u32 },
+    Quantize { colors: usize },
+    // merges the regions smaller than min_area into a neighbour, the regions are those of a
+    // sample fill with the same metric, tolerance and connectivity
+    MergeRegions(filter::MergeRegions),
+    // fills every region with the closest sample according to metric, that the tag rules allow,
+    // tolerance is the maximum distance for a pixel to belong to the region, connectivity
+    // whether pixels that only share a corner are connected
+    SampleFill(filter::SampleFill),
+    // a filter from the FilterRegistry
+    Custom {
+        name: String,
+        #[serde(default)]
+        params: Value,
+    },
+}
+
+impl Stage {
+    fn is_sample_fill(&self) -> bool {
+        matches!(self, Stage::SampleFill(_))
+    }
+
+    // the filter of a sample fill stage
+    fn sample_fill(&self) -> Option<&filter::SampleFill> {
+        match self {
+            Stage::SampleFill(fill) => Some(fill),
+            _ => None,
+        }
+    }
+
+    pub fn filter(&self, registry: &FilterRegistry) -> Result<Box<dyn Filter>, SpiegelError> {
+        Ok(match self {
+            Stage::GaussianBlur { sigma } => Box::new(filter::GaussianBlur { sigma: *sigma }),
+            Stage::Median { x_radius, y_radius } => Box::new(filter::Median { x_radius: *x_radius, y_radius: *y_radius }),
+            Stage::Quantize { colors } => Box::new(filter::Quantize { colors: *colors }),
+            Stage::MergeRegions(merge) => Box::new(merge.clone()),
+            Stage::SampleFill(fill) => Box::new(fill.clone()),
+            Stage::Custom { name, params } => registry.create(name, params)?,
+        })
+    }
+}
+
+// An ordered list of stages, that can be stored as json, so that the same recipe
+// runs in the browser and on the command line.
+//
+// Samples are loaded asynchronously in the browser, so running a pipeline is split up:
+// prepare runs everything before the first sample fill, and resume runs a sample fill and
+// the stages up to the next one, once the samples that fill needs are in the context.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub stages: Vec<Stage>,
+}
+
+impl Default for Pipeline {
+    // the original spiegel filter
+    fn default() -> Self {
+        Self {
+            stages: vec![
+                Stage::GaussianBlur { sigma: 2.0 },
+                Stage::Median { x_radius: 2, y_radius: 2 },
+                Stage::Quantize { colors: 256 },
+                Stage::SampleFill(filter::SampleFill::default()),
+            ],
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self { stages: vec![] }
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn push(&mut self, stage: Stage) {
+        self.stages.push(stage);
+    }
+
+    pub fn insert(&mut self, index: usize, stage: Stage) {
+        self.stages.insert(index, stage);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Stage {
+        self.stages.remove(index)
+    }
+
+    // moves the stage at index from to index to, shifting the stages in between
+    pub fn move_stage(&mut self, from: usize, to: usize) {
+        let stage = self.stages.remove(from);
+        self.stages.insert(to, stage);
+    }
+
+    // Runs the pipeline in one go, with the samples of provider. Before every sample fill,
+    // the samples that are not in the store yet are loaded all at once, and added to it.
+    pub async fn apply(
+        &self,
+        src: &RgbImage,
+        provider: &dyn SampleProvider,
+        store: &mut SampleStore,
+        registry: &FilterRegistry,
+    ) -> Result<RgbImage, SpiegelError> {
+        let mut ctx = FilterContext::new(src, provider.catalog());
+        let mut image = self.prepare(src, &mut ctx, registry)?;
+        let mut fill = self.next_fill(0);
+        while let Some(index) = fill {
+            let missing = self.gather_samples(index, &image, &mut ctx, store);
+            for sample in provider.load_all(&missing).await {
+                ctx.add_sample(store.insert(sample?));
+            }
+            image = self.resume(index, image, &mut ctx, registry)?;
+            fill = self.next_fill(index + 1);
+        }
+        Ok(image)
+    }
+
+    // runs all stages before the first sample fill
+    pub fn prepare(&self, src: &RgbImage, ctx: &mut FilterContext, registry: &FilterRegistry) -> Result<RgbImage, SpiegelError> {
+        let end = self.next_fill(0).unwrap_or(self.stages.len());
+        run(&self.stages[..end], src.clone(), ctx, registry)
+    }
+
+    // the index of the first sample fill from stage from on
+    pub fn next_fill(&self, from: usize) -> Option<usize> {
+        self.stages.iter().skip(from).position(Stage::is_sample_fill).map(|i| from + i)
+    }
+
+    // the samples that the sample fill at stage fill needs for the image it gets
+    pub fn required_samples<'a>(&self, fill: usize, image: &RgbImage, ctx: &'a mut FilterContext) -> Vec<&'a SampleEntry> {
+        match self.stages.get(fill).and_then(Stage::sample_fill) {
+            Some(fill) => {
+                // the sample fill gets the same choice from ctx
+                let chosen = ctx.chosen_samples(image, fill);
+                let ctx: &'a FilterContext = ctx;
+                transform::required_samples(&ctx.catalog, &chosen)
+            }
+            None => vec![],
+        }
+    }
+
+    // Puts the samples that the sample fill at stage fill needs and that are in the store
+    // into ctx, and returns the entries of the ones that still have to be loaded.
+    pub fn gather_samples(&self, fill: usize, image: &RgbImage, ctx: &mut FilterContext, store: &mut SampleStore) -> Vec<SampleEntry> {
+        let required: Vec<SampleEntry> = self.required_samples(fill, image, ctx).into_iter().cloned().collect();
+        let mut missing = vec![];
+        for entry in required {
+            match store.get(&entry.id) {
+                Some(sample) => ctx.add_sample(sample),
+                None => missing.push(entry),
+            }
+        }
+        missing
+    }
+
+    // runs the sample fill at stage fill and the stages after it, up to the next sample fill
+    pub fn resume(&self, fill: usize, image: RgbImage, ctx: &mut FilterContext, registry: &FilterRegistry) -> Result<RgbImage, SpiegelError> {
+        let end = self.next_fill(fill + 1).unwrap_or(self.stages.len());
+        run(&self.stages[fill..end], image, ctx, registry)
+    }
+}
+
+fn run(stages: &[Stage], src: RgbImage, ctx: &mut FilterContext, registry: &FilterRegistry) -> Result<RgbImage, SpiegelError> {
+    let mut image = src;
+    for stage in stages {
+        image = stage.filter(registry)?.apply(&image, ctx)?;
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use image::{Pixel, Rgb};
+
+    use super::*;
+    use crate::provider::MemoryProvider;
+    use crate::samples::CacheStats;
+
+    fn provider() -> MemoryProvider {
+        let mut provider = MemoryProvider::new();
+        provider.insert(SampleEntry::new("dark", [0x30, 0x30, 0x30], [4, 4]), RgbImage::from_pixel(4, 4, Rgb([1, 2, 3])));
+        provider.insert(SampleEntry::new("light", [0xe0, 0xe0, 0xe0], [4, 4]), RgbImage::from_pixel(4, 4, Rgb([4, 5, 6])));
+        provider
+    }
+
+    #[test]
+    fn apply_fills_with_loaded_sample() {
+        let src = RgbImage::from_pixel(8, 8, Rgb([0x30, 0x30, 0x30]));
+        let mut store = SampleStore::default();
+        let out = block_on(Pipeline::default().apply(&src, &provider(), &mut store, &FilterRegistry::default())).unwrap();
+
+        assert!(store.contains("dark"));
+        assert!(!store.contains("light"));
+        assert!(out.pixels().all(|pixel| pixel.channels() == [1, 2, 3]));
+
+        // the second time the sample comes from the store
+        block_on(Pipeline::default().apply(&src, &provider(), &mut store, &FilterRegistry::default())).unwrap();
+        assert_eq!(CacheStats { hits: 1, misses: 1, evictions: 0 }, store.stats());
+    }
+
+    #[test]
+    fn samples_are_loaded_for_every_sample_fill() {
+        let mut registry = FilterRegistry::default();
+        registry.register("invert", |_| Ok(Box::new(Invert)));
+        let fill = Stage::SampleFill(filter::SampleFill::default());
+        let invert = Stage::Custom { name: "invert".to_owned(), params: Value::Null };
+        let pipeline = Pipeline { stages: vec![fill.clone(), invert, fill] };
+        assert_eq!((Some(0), Some(2), None), (pipeline.next_fill(0), pipeline.next_fill(1), pipeline.next_fill(3)));
+
+        // the first fill needs the dark sample, the second one the light sample for the inverted image
+        let src = RgbImage::from_pixel(8, 8, Rgb([0x30, 0x30, 0x30]));
+        let mut store = SampleStore::default();
+        let out = block_on(pipeline.apply(&src, &provider(), &mut store, &registry)).unwrap();
+        assert!(store.contains("dark") && store.contains("light"));
+        assert!(out.pixels().all(|pixel| pixel.channels() == [4, 5, 6]));
+    }
+
+    #[test]
+    fn blur_rejects_sigma_that_is_not_positive() {
+        let src = RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]));
+        for sigma in [0.0, -1.0, f32::NAN] {
+            let pipeline = Pipeline { stages: vec![Stage::GaussianBlur { sigma }] };
+            let result = block_on(pipeline.apply(&src, &provider(), &mut SampleStore::default(), &FilterRegistry::default()));
+            assert!(matches!(result, Err(SpiegelError::InvalidParameters(name, _)) if name == "gaussian_blur"));
+        }
+    }
+
+    #[test]
+    fn stages_roundtrip_through_json() {
+        let mut pipeline = Pipeline::default();
+        pipeline.move_stage(1, 0);
+        pipeline.remove(3);
+        pipeline.push(Stage::GaussianBlur { sigma: 0.5 });
+
+        let json = pipeline.to_json().unwrap();
+        assert!(json.contains("\"stage\": \"median\""));
+        assert_eq!(pipeline, Pipeline::from_json(&json).unwrap());
+        assert_eq!(Stage::Median { x_radius: 2, y_radius: 2 }, pipeline.stages[0]);
+    }
+
+    struct Invert;
+
+    impl Filter for Invert {
+        fn apply(&self, img: &RgbImage, _ctx: &mut FilterContext) -> Result<RgbImage, SpiegelError> {
+            let mut inverted = img.clone();
+            image::imageops::invert(&mut inverted);
+            Ok(inverted)
+        }
+    }
+
+    #[test]
+    fn custom_filters_are_created_by_name() {
+        let mut registry = FilterRegistry::default();
+        registry.register("invert", |_| Ok(Box::new(Invert)));
+        let pipeline = Pipeline::from_json(r#"{"stages": [
+            {"stage": "custom", "name": "invert"},
+            {"stage": "custom", "name": "median", "params": {"x_radius": 1, "y_radius": 1}}
+        ]}"#).unwrap();
+
+        let src = RgbImage::from_pixel(4, 4, Rgb([10, 20, 30]));
+        let out = block_on(pipeline.apply(&src, &provider(), &mut SampleStore::default(), &registry)).unwrap();
+        assert_eq!(&Rgb([245, 235, 225]), out.get_pixel(2, 2));
+
+        let unknown = Pipeline { stages: vec![Stage::Custom { name: "sepia".to_owned(), params: Value::Null }] };
+        assert!(matches!(block_on(unknown.apply(&src, &provider(), &mut SampleStore::default(), &registry)), Err(SpiegelError::UnknownFilter(_))));
+        // the sample fill only runs as a stage of its own, that gets its samples
+        assert!(!registry.contains("sample_fill"));
+    }
+}
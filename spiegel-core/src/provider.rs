@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use futures::future::{self, join_all, LocalBoxFuture};
+use image::{Rgb, RgbImage};
+
+use crate::catalog::{Catalog, SampleEntry};
+use crate::color::DistanceMetric;
+use crate::error::SpiegelError;
+use crate::pack::PackWeight;
+use crate::samples::ColorSample;
+use crate::{library, tags};
+
+// Where the textures come from. A provider lists its samples in a catalog, and loads the
+// image of any of them on request. Loading is asynchronous, so that a provider can fetch
+// the images over the network without blocking.
+pub trait SampleProvider {
+    fn catalog(&self) -> Arc<Catalog>;
+
+    fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>>;
+
+    // loads many samples at once, providers can override this when they can share work between them
+    fn load_all<'a>(&'a self, entries: &'a [SampleEntry]) -> LocalBoxFuture<'a, Vec<Result<ColorSample, SpiegelError>>> {
+        Box::pin(join_all(entries.iter().map(|entry| self.load(entry))))
+    }
+
+    // the sample closest to color, a scan of the catalog: building an index for a single
+    // lookup would take longer
+    fn get<'a>(&'a self, color: &Rgb<u8>, metric: DistanceMetric) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+        let catalog = self.catalog();
+        let nearest = catalog.samples.iter()
+            .min_by(|a, b| metric.distance(color, &a.color()).total_cmp(&metric.distance(color, &b.color())));
+        match nearest.cloned() {
+            Some(entry) => Box::pin(async move { self.load(&entry).await }),
+            None => Box::pin(future::ready(Err(SpiegelError::MissingSample(format!("{:?}", color.0))))),
+        }
+    }
+}
+
+// samples held in memory, for tests and for textures that the user brings along
+#[derive(Clone, Default)]
+pub struct MemoryProvider {
+    catalog: Arc<Catalog>,
+    images: HashMap<String, Arc<RgbImage>>,
+}
+
+impl MemoryProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, entry: SampleEntry, image: RgbImage) {
+        self.images.insert(entry.id.clone(), Arc::new(image));
+        Arc::make_mut(&mut self.catalog).samples.push(entry);
+    }
+
+    pub fn len(&self) -> usize {
+        self.catalog.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.catalog.samples.is_empty()
+    }
+}
+
+impl SampleProvider for MemoryProvider {
+    fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.clone()
+    }
+
+    fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+        let sample = match self.images.get(&entry.id) {
+            Some(image) => Ok(ColorSample::new(entry, image.as_ref().clone())),
+            None => Err(SpiegelError::MissingSample(entry.id.clone())),
+        };
+        Box::pin(future::ready(sample))
+    }
+}
+
+// The samples of several providers as one catalog. When providers have samples with the
+// same id, the first one has it. Combined packs keep the samples of every pack apart, by
+// putting the name of the pack in front of the ids.
+pub struct CombinedProvider {
+    providers: Vec<Rc<dyn SampleProvider>>,
+    catalogs: Vec<Arc<Catalog>>,
+    catalog: Arc<Catalog>,
+    // the provider of every sample, and its position in the catalog of that provider
+    owners: HashMap<String, (usize, usize)>,
+}
+
+impl CombinedProvider {
+    pub fn new(providers: Vec<Rc<dyn SampleProvider>>) -> Self {
+        Self::combine(providers.into_iter().map(|provider| (None, provider)).collect())
+    }
+
+    // the samples of each pack get the pack and the ids <pack>/<id>
+    pub fn packs(packs: Vec<(PackWeight, Rc<dyn SampleProvider>)>) -> Self {
+        Self::combine(packs.into_iter().map(|(weight, provider)| (Some(weight), provider)).collect())
+    }
+
+    fn combine(providers: Vec<(Option<PackWeight>, Rc<dyn SampleProvider>)>) -> Self {
+        let catalogs: Vec<Arc<Catalog>> = providers.iter().map(|(_, provider)| provider.catalog()).collect();
+        let mut catalog = Catalog::default();
+        let mut owners = HashMap::new();
+        for (p, ((pack, _), provider_catalog)) in providers.iter().zip(&catalogs).enumerate() {
+            match pack {
+                Some(pack) => catalog.packs.push(pack.clone()),
+                None => catalog.packs.extend(provider_catalog.packs.iter().cloned()),
+            }
+            for (i, entry) in provider_catalog.samples.iter().enumerate() {
+                let entry = match pack {
+                    Some(pack) => SampleEntry { id: format!("{}/{}", pack.name, entry.id), pack: Some(pack.name.clone()), ..entry.clone() },
+                    None => entry.clone(),
+                };
+                if !owners.contains_key(&entry.id) {
+                    owners.insert(entry.id.clone(), (p, i));
+                    // atlas rects are about the pages of the owner
+                    catalog.samples.push(SampleEntry { atlas: None, ..entry });
+                }
+            }
+        }
+        let providers = providers.into_iter().map(|(_, provider)| provider).collect();
+        Self { providers, catalogs, catalog: Arc::new(catalog), owners }
+    }
+
+    // the entry as the provider that owns it knows it
+    fn owned(&self, id: &str) -> Option<(usize, &SampleEntry)> {
+        self.owners.get(id).map(|(p, i)| (*p, &self.catalogs[*p].samples[*i]))
+    }
+}
+
+impl SampleProvider for CombinedProvider {
+    fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.clone()
+    }
+
+    fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+        match self.owned(&entry.id) {
+            Some((p, owned)) => Box::pin(async move { self.providers[p].load(owned).await.map(|sample| renamed(sample, &entry.id)) }),
+            None => Box::pin(future::ready(Err(SpiegelError::MissingSample(entry.id.clone())))),
+        }
+    }
+
+    // every provider loads its share of the entries at once
+    fn load_all<'a>(&'a self, entries: &'a [SampleEntry]) -> LocalBoxFuture<'a, Vec<Result<ColorSample, SpiegelError>>> {
+        Box::pin(async move {
+            let mut shares: Vec<Vec<SampleEntry>> = vec![vec![]; self.providers.len()];
+            let mut ids: Vec<Vec<&str>> = vec![vec![]; self.providers.len()];
+            let mut unknown = vec![];
+            for entry in entries {
+                match self.owned(&entry.id) {
+                    Some((p, owned)) => {
+                        shares[p].push(owned.clone());
+                        ids[p].push(&entry.id);
+                    }
+                    None => unknown.push(Err(SpiegelError::MissingSample(entry.id.clone()))),
+                }
+            }
+            let loaded = join_all(self.providers.iter().zip(&shares).map(|(provider, share)| provider.load_all(share))).await;
+            loaded
+                .into_iter()
+                .zip(ids)
+                .flat_map(|(samples, ids)| samples.into_iter().zip(ids))
+                .map(|(sample, id)| sample.map(|sample| renamed(sample, id)))
+                .chain(unknown)
+                .collect()
+        })
+    }
+}
+
+// a sample of a provider, with its id in the combined catalog
+fn renamed(sample: ColorSample, id: &str) -> ColorSample {
+    ColorSample { id: id.to_owned(), ..sample }
+}
+
+// The samples of another provider, made seamless as they are loaded (see library::seamless),
+// for libraries whose textures show seams when they are tiled.
+pub struct SeamlessProvider {
+    inner: Box<dyn SampleProvider>,
+}
+
+impl SeamlessProvider {
+    pub fn new(inner: Box<dyn SampleProvider>) -> Self {
+        Self { inner }
+    }
+}
+
+impl SampleProvider for SeamlessProvider {
+    fn catalog(&self) -> Arc<Catalog> {
+        self.inner.catalog()
+    }
+
+    fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+        Box::pin(async move { self.inner.load(entry).await.map(made_seamless) })
+    }
+
+    fn load_all<'a>(&'a self, entries: &'a [SampleEntry]) -> LocalBoxFuture<'a, Vec<Result<ColorSample, SpiegelError>>> {
+        Box::pin(async move {
+            let loaded = self.inner.load_all(entries).await;
+            loaded.into_iter().map(|sample| sample.map(made_seamless)).collect()
+        })
+    }
+}
+
+fn made_seamless(sample: ColorSample) -> ColorSample {
+    ColorSample { image: library::seamless(&sample.image), ..sample }
+}
+
+// Generates textures instead of loading them: every color of the catalog gets a seamless
+// pattern of waves around it, which is the same for the same color every time.
+pub struct ProceduralProvider {
+    catalog: Arc<Catalog>,
+    // side of the generated samples
+    size: u32,
+    // how far the pattern goes above and below the color, per channel
+    amplitude: f32,
+}
+
+impl Default for ProceduralProvider {
+    fn default() -> Self {
+        Self::new(12, 128, 12.0)
+    }
+}
+
+impl ProceduralProvider {
+    // a catalog of levels³ colors, spread evenly over the RGB cube
+    pub fn new(levels: u8, size: u32, amplitude: f32) -> Self {
+        let levels = levels.max(2) as u32;
+        let level = |i: u32| (i * 255 / (levels - 1)) as u8;
+        let mut catalog = Catalog::default();
+        for r in 0..levels {
+            for g in 0..levels {
+                for b in 0..levels {
+                    let mean = [level(r), level(g), level(b)];
+                    let id = format!("{:02x}{:02x}{:02x}", mean[0], mean[1], mean[2]);
+                    let tags = vec![format!("{}:generated", tags::SOURCE)];
+                    catalog.samples.push(SampleEntry { tags, ..SampleEntry::new(&id, mean, [size, size]) });
+                }
+            }
+        }
+        Self { catalog: Arc::new(catalog), size, amplitude }
+    }
+
+    pub fn generate(&self, entry: &SampleEntry) -> RgbImage {
+        let mut seed = entry.id.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+        let mut next = |range: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % range
+        };
+        // whole periods across the sample, so that it tiles without seams
+        let waves: Vec<(f32, f32, f32)> = (0..3)
+            .map(|_| ((next(5) + 1) as f32, next(5) as f32, next(628) as f32 / 100.0))
+            .collect();
+        let size = self.size as f32;
+        RgbImage::from_fn(self.size, self.size, |x, y| {
+            let wave: f32 = waves
+                .iter()
+                .map(|(fx, fy, phase)| (TAU * (fx * x as f32 + fy * y as f32) / size + phase).sin())
+                .sum::<f32>() / waves.len() as f32;
+            let channel = |c: usize| (entry.mean[c] as f32 + wave * self.amplitude).round().clamp(0.0, 255.0) as u8;
+            Rgb([channel(0), channel(1), channel(2)])
+        })
+    }
+}
+
+impl SampleProvider for ProceduralProvider {
+    fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.clone()
+    }
+
+    fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+        Box::pin(future::ready(Ok(ColorSample::new(entry, self.generate(entry)))))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use directory::{open_packs, DirectoryProvider};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod directory {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    use futures::future::{self, LocalBoxFuture};
+    use image::RgbImage;
+
+    use crate::atlas;
+    use crate::catalog::{Catalog, SampleEntry};
+    use crate::error::SpiegelError;
+    use crate::pack::{PackList, PackWeight, PACKS_FILE};
+    use crate::samples::ColorSample;
+
+    use super::{CombinedProvider, SampleProvider};
+
+    // A sample library on the local disk: a directory with a manifest.json. Samples in the
+    // atlas are cut out of its pages. A page is only kept while its samples are cut out, so
+    // that only the samples count against the budget of the SampleStore.
+    pub struct DirectoryProvider {
+        dir: PathBuf,
+        catalog: Arc<Catalog>,
+    }
+
+    impl DirectoryProvider {
+        pub fn open(dir: &Path) -> Result<Self, SpiegelError> {
+            let catalog = Catalog::from_json(&fs::read_to_string(dir.join("manifest.json"))?)?;
+            Ok(Self { dir: dir.to_owned(), catalog: Arc::new(catalog) })
+        }
+
+        fn read(&self, entry: &SampleEntry) -> Result<RgbImage, SpiegelError> {
+            match (entry.atlas, self.catalog.page(entry)) {
+                (Some(rect), Some(file)) => Ok(atlas::slice(&self.decode(entry, file)?, &rect)),
+                _ => self.decode(entry, &entry.file),
+            }
+        }
+
+        // the image file, for entry
+        fn decode(&self, entry: &SampleEntry, file: &str) -> Result<RgbImage, SpiegelError> {
+            let image = image::open(self.dir.join(file)).map_err(|e| SpiegelError::SampleLoad(entry.id.clone(), Box::new(e)))?;
+            Ok(image.to_rgb8())
+        }
+    }
+
+    impl SampleProvider for DirectoryProvider {
+        fn catalog(&self) -> Arc<Catalog> {
+            self.catalog.clone()
+        }
+
+        fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+            Box::pin(future::ready(self.read(entry).map(|image| ColorSample::new(entry, image))))
+        }
+
+        // every atlas page is decoded once for all of its samples, and dropped before the next one
+        fn load_all<'a>(&'a self, entries: &'a [SampleEntry]) -> LocalBoxFuture<'a, Vec<Result<ColorSample, SpiegelError>>> {
+            let mut samples: Vec<Option<Result<ColorSample, SpiegelError>>> = entries.iter().map(|_| None).collect();
+            let mut pages: HashMap<usize, Vec<usize>> = HashMap::new();
+            for (i, entry) in entries.iter().enumerate() {
+                if let (Some(rect), Some(_)) = (entry.atlas, self.catalog.page(entry)) {
+                    pages.entry(rect.page).or_default().push(i);
+                }
+            }
+            for (page, on_page) in &pages {
+                match self.decode(&entries[on_page[0]], &self.catalog.atlas[*page]) {
+                    Ok(page) => {
+                        for &i in on_page {
+                            let rect = entries[i].atlas.expect("an entry in the atlas");
+                            samples[i] = Some(Ok(ColorSample::new(&entries[i], atlas::slice(&page, &rect))));
+                        }
+                    }
+                    Err(error) => {
+                        for &i in on_page {
+                            samples[i] = Some(Err(SpiegelError::SampleLoad(entries[i].id.clone(), error.to_string().into())));
+                        }
+                    }
+                }
+            }
+            let samples = samples
+                .into_iter()
+                .zip(entries)
+                .map(|(sample, entry)| sample.unwrap_or_else(|| self.read(entry).map(|image| ColorSample::new(entry, image))))
+                .collect();
+            Box::pin(future::ready(samples))
+        }
+    }
+
+    // the selected packs of the packs.json in root, all of them when nothing is selected
+    pub fn open_packs(root: &Path, selection: &[PackWeight]) -> Result<CombinedProvider, SpiegelError> {
+        let list = PackList::from_json(&fs::read_to_string(root.join(PACKS_FILE))?)?;
+        let mut packs: Vec<(PackWeight, Rc<dyn SampleProvider>)> = vec![];
+        for (pack, weight) in list.select(selection)? {
+            packs.push((weight, Rc::new(DirectoryProvider::open(&root.join(&pack.dir))?)));
+        }
+        Ok(CombinedProvider::packs(packs))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::atlas::AtlasRect;
+
+    #[test]
+    fn memory_provider_finds_closest() {
+        let mut provider = MemoryProvider::new();
+        provider.insert(SampleEntry::new("red", [200, 0, 0], [2, 2]), RgbImage::from_pixel(2, 2, Rgb([200, 0, 0])));
+        provider.insert(SampleEntry::new("blue", [0, 0, 200], [2, 2]), RgbImage::from_pixel(2, 2, Rgb([0, 0, 200])));
+
+        let sample = block_on(provider.get(&Rgb([10, 20, 150]), DistanceMetric::Rgb)).unwrap();
+        assert_eq!("blue", sample.id);
+        assert!(matches!(block_on(provider.load(&SampleEntry::new("green", [0, 200, 0], [2, 2]))), Err(SpiegelError::MissingSample(_))));
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn directory_provider_cuts_samples_out_of_the_pages() {
+        let dir = std::env::temp_dir().join(format!("spiegel-directory-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        RgbImage::from_fn(4, 2, |x, _| Rgb([x as u8 * 50, 0, 0])).save(dir.join("atlas-0.png")).unwrap();
+        RgbImage::from_pixel(2, 2, Rgb([0, 0, 90])).save(dir.join("loose.png")).unwrap();
+        let mut catalog = Catalog::default();
+        catalog.atlas.push("atlas-0.png".to_owned());
+        for (id, x) in [("left", 0), ("right", 2)] {
+            let atlas = Some(AtlasRect { page: 0, x, y: 0, width: 2, height: 2 });
+            catalog.samples.push(SampleEntry { atlas, ..SampleEntry::new(id, [0, 0, 0], [2, 2]) });
+        }
+        catalog.samples.push(SampleEntry { file: "loose.png".to_owned(), ..SampleEntry::new("loose", [0, 0, 90], [2, 2]) });
+        std::fs::write(dir.join("manifest.json"), catalog.to_json().unwrap()).unwrap();
+
+        let provider = DirectoryProvider::open(&dir).unwrap();
+        let entries = &provider.catalog().samples;
+        let loaded: Vec<(String, [u8; 3])> = block_on(provider.load_all(entries))
+            .into_iter()
+            .map(|sample| sample.unwrap())
+            .map(|sample| (sample.id.clone(), sample.image.get_pixel(1, 1).0))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(vec![("left".to_owned(), [50, 0, 0]), ("right".to_owned(), [150, 0, 0]), ("loose".to_owned(), [0, 0, 90])], loaded);
+    }
+
+    #[test]
+    fn combined_provider_loads_from_the_owner() {
+        let mut first = MemoryProvider::new();
+        first.insert(SampleEntry::new("shared", [1, 1, 1], [1, 1]), RgbImage::from_pixel(1, 1, Rgb([1, 1, 1])));
+        let mut second = MemoryProvider::new();
+        second.insert(SampleEntry::new("shared", [2, 2, 2], [1, 1]), RgbImage::from_pixel(1, 1, Rgb([2, 2, 2])));
+        second.insert(SampleEntry::new("own", [3, 3, 3], [1, 1]), RgbImage::from_pixel(1, 1, Rgb([3, 3, 3])));
+        let (first, second): (Rc<dyn SampleProvider>, Rc<dyn SampleProvider>) = (Rc::new(first), Rc::new(second));
+
+        let combined = CombinedProvider::new(vec![first.clone(), second.clone()]);
+        let catalog = combined.catalog();
+        let ids: Vec<&str> = catalog.samples.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(vec!["shared", "own"], ids);
+        let mut loaded: Vec<[u8; 3]> = block_on(combined.load_all(&catalog.samples))
+            .into_iter()
+            .map(|sample| sample.unwrap().image.get_pixel(0, 0).0)
+            .collect();
+        loaded.sort();
+        assert_eq!(vec![[1, 1, 1], [3, 3, 3]], loaded);
+
+        // as packs, both samples named shared are there
+        let packs = CombinedProvider::packs(vec![(PackWeight::new("a", 1.0), first), (PackWeight::new("b", 2.0), second)]);
+        let catalog = packs.catalog();
+        assert_eq!(Some(2.0), catalog.weight("b"));
+        let shared = catalog.get("b/shared").unwrap();
+        assert_eq!(Some("b"), shared.pack.as_deref());
+        let sample = block_on(packs.load(shared)).unwrap();
+        assert_eq!(("b/shared", [2, 2, 2]), (sample.id.as_str(), sample.image.get_pixel(0, 0).0));
+    }
+
+    #[test]
+    fn seamless_provider_blends_the_samples() {
+        let mut memory = MemoryProvider::new();
+        let stripes = RgbImage::from_fn(4, 4, |x, _| Rgb([x as u8 * 60, 0, 0]));
+        memory.insert(SampleEntry::new("stripes", [90, 0, 0], [4, 4]), stripes.clone());
+        let provider = SeamlessProvider::new(Box::new(memory));
+        let entry = provider.catalog().samples[0].clone();
+        let sample = block_on(provider.load(&entry)).unwrap();
+        assert_eq!(("stripes", library::seamless(&stripes)), (sample.id.as_str(), sample.image));
+        assert_eq!(1, block_on(provider.load_all(&[entry])).len());
+    }
+
+    #[test]
+    fn procedural_samples_keep_their_color() {
+        let provider = ProceduralProvider::default();
+        assert_eq!(12 * 12 * 12, provider.catalog().samples.len());
+
+        let sample = block_on(provider.get(&Rgb([100, 140, 60]), DistanceMetric::Cie76)).unwrap();
+        let measured = library::measure(&sample.image);
+        assert!(library::drift(&Rgb([sample.r, sample.g, sample.b]), &measured) < 1.0);
+        assert!(measured.variance > 10.0);
+        assert_eq!(sample.image, block_on(provider.get(&Rgb([100, 140, 60]), DistanceMetric::Cie76)).unwrap().image);
+    }
+}
@@ -65,7 +65,6 @@ impl OctTreeQuantizer {
             P: Pixel<Subpixel = u8> + 'static,
     {
         fn get_index_for_color<P>(
-            quantizer: &OctTreeQuantizer,
             color: &P,
             level: usize,
             node: &Rc<RefCell<OctTreeNode>>,
@@ -85,16 +84,16 @@ impl OctTreeQuantizer {
             if let Some(child) = child {
                 let child_b = child.borrow();
                 if child_b.is_leaf {
-                    return Some(child_b.index);
+                    Some(child_b.index)
                 } else {
-                    return get_index_for_color(quantizer, color, level + 1, child);
+                    get_index_for_color(color, level + 1, child)
                 }
             } else {
-                return Some(node_b.index);
+                Some(node_b.index)
             }
         }
 
-        get_index_for_color(&self, color, 0, node)
+        get_index_for_color(color, 0, node)
     }
 
     fn build_color_table(&mut self) -> Vec<Option<Rgb<u8>>> {
@@ -191,41 +190,36 @@ impl OctTreeQuantizer {
 
                 if level < MAX_LEVEL {
                     insert_color(quantizer, color, level + 1, clone);
-                } else {
-                    return;
                 }
-            } else {
-                if node
-                    .borrow()
+            } else if node
+                .borrow()
+                .leaf
+                .get(index)
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .borrow()
+                .is_leaf
+            {
+                let mut node = node.borrow_mut();
+                let mut child = node
                     .leaf
-                    .get(index)
+                    .get_mut(index)
                     .unwrap()
                     .as_ref()
                     .unwrap()
-                    .borrow()
-                    .is_leaf
-                {
-                    let mut node = node.borrow_mut();
-                    let mut child = node
-                        .leaf
-                        .get_mut(index)
-                        .unwrap()
-                        .as_ref()
-                        .unwrap()
-                        .borrow_mut();
-                    child.count += 1;
-                    child.total_red += color.channels()[0] as u32;
-                    child.total_green += color.channels()[1] as u32;
-                    child.total_blue += color.channels()[2] as u32;
-                    return;
-                } else {
-                    insert_color(
-                        quantizer,
-                        color,
-                        level + 1,
-                        Rc::clone(&(node.borrow().leaf[index]).as_ref().unwrap()),
-                    );
-                }
+                    .borrow_mut();
+                child.count += 1;
+                child.total_red += color.channels()[0] as u32;
+                child.total_green += color.channels()[1] as u32;
+                child.total_blue += color.channels()[2] as u32;
+            } else {
+                insert_color(
+                    quantizer,
+                    color,
+                    level + 1,
+                    Rc::clone(node.borrow().leaf[index].as_ref().unwrap()),
+                );
             }
         }
 
@@ -237,57 +231,52 @@ impl OctTreeQuantizer {
         fn reduce_tree(quantizer: &mut OctTreeQuantizer, num_colors: usize, level: isize) {
             if level < 0 {
                 return;
-            } else {
-                let mut removals = Vec::new();
-                let list = &quantizer.color_list[level as usize];
-                for node in list {
-                    if let Some(node) = node {
-                        if node.borrow().children > 0 {
-                            for i in 0..8 {
-                                let mut color: Option<(usize, u32, u32, u32, usize)> = None;
-
-                                if let Some(child) = node.borrow().leaf.get(i) {
-                                    if let Some(child) = child {
-                                        let child = child.borrow();
-                                        color = Some((
-                                            child.count,
-                                            child.total_red,
-                                            child.total_green,
-                                            child.total_blue,
-                                            child.p_index,
-                                        ));
-                                    }
-                                }
-
-                                // need to mutate node, which conflicts with previous borrow to retrieve the child
-                                if let Some(color) = color {
-                                    let mut node = node.borrow_mut();
-                                    node.count += color.0;
-                                    node.total_red += color.1;
-                                    node.total_green += color.2;
-                                    node.total_blue += color.3;
-                                    node.leaf[i] = None;
-                                    node.children -= 1;
-                                    quantizer.colors -= 1;
-
-                                    removals.push(color.4); //save for further processing outside loop (and borrow of colorlist)
-                                }
-                            }
-                            node.borrow_mut().is_leaf = true;
-                            quantizer.colors += 1;
-                            if quantizer.colors <= num_colors {
-                                return;
-                            }
+            }
+            let mut removals = Vec::new();
+            let list = &quantizer.color_list[level as usize];
+            for node in list.iter().flatten() {
+                if node.borrow().children > 0 {
+                    for i in 0..8 {
+                        let mut color: Option<(usize, u32, u32, u32, usize)> = None;
+
+                        if let Some(Some(child)) = node.borrow().leaf.get(i) {
+                            let child = child.borrow();
+                            color = Some((
+                                child.count,
+                                child.total_red,
+                                child.total_green,
+                                child.total_blue,
+                                child.p_index,
+                            ));
+                        }
+
+                        // need to mutate node, which conflicts with previous borrow to retrieve the child
+                        if let Some(color) = color {
+                            let mut node = node.borrow_mut();
+                            node.count += color.0;
+                            node.total_red += color.1;
+                            node.total_green += color.2;
+                            node.total_blue += color.3;
+                            node.leaf[i] = None;
+                            node.children -= 1;
+                            quantizer.colors -= 1;
+
+                            removals.push(color.4); //save for further processing outside loop (and borrow of colorlist)
                         }
                     }
+                    node.borrow_mut().is_leaf = true;
+                    quantizer.colors += 1;
+                    if quantizer.colors <= num_colors {
+                        return;
+                    }
                 }
-                let color_list = &mut quantizer.color_list[(level as usize + 1)];
-                for index in removals {
-                    color_list[index] = None; //set to None here, Option removed later
-                }
-
-                reduce_tree(quantizer, num_colors, level - 1);
             }
+            let color_list = &mut quantizer.color_list[level as usize + 1];
+            for index in removals {
+                color_list[index] = None; //set to None here, Option removed later
+            }
+
+            reduce_tree(quantizer, num_colors, level - 1);
         }
 
         // call to nested function
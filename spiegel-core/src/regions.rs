@@ -0,0 +1,432 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+use image::{Rgb, RgbImage};
+use imageproc::point::Point;
+use imageproc::rect::Rect;
+use serde::{Deserialize, Serialize};
+
+use crate::color::DistanceMetric;
+
+// the neighbours that share an edge with a pixel, and those that share a corner
+const EDGES: [(i64, i64); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const EDGES_AND_CORNERS: [(i64, i64); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+// Which pixels are connected: those that share an edge, or also those that only share a corner.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Connectivity {
+    #[default]
+    #[serde(rename = "4")]
+    Four,
+    #[serde(rename = "8")]
+    Eight,
+}
+
+impl Connectivity {
+    fn neighbours(&self) -> &'static [(i64, i64)] {
+        match self {
+            Connectivity::Four => &EDGES,
+            Connectivity::Eight => &EDGES_AND_CORNERS,
+        }
+    }
+}
+
+// How a region that is too small picks the neighbour it is merged into: the one closest
+// to it in color, or the one it shares the longest border with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeBy {
+    #[default]
+    Color,
+    Border,
+}
+
+// A region is the connected pixels within tolerance of the color of its first pixel,
+// the first in the order of the rows that no earlier region took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionStats {
+    pub first: Point<u32>,
+    // the color of the first pixel, which a merged region keeps from the neighbour it went into
+    pub color: Rgb<u8>,
+    // the average color of all its pixels
+    pub mean: Rgb<u8>,
+    pub area: u32,
+    pub bbox: Rect,
+    // the average position of its pixels
+    pub centroid: Point<f32>,
+    // the number of pixel edges between the region and other regions or the border of the image
+    pub perimeter: u32,
+}
+
+// Every pixel of an image labeled with its region. The labels are the positions of the
+// regions, which are in the order of their first pixels.
+#[derive(Debug, Clone)]
+pub struct RegionMap {
+    width: u32,
+    height: u32,
+    labels: Vec<usize>,
+    regions: Vec<RegionStats>,
+}
+
+impl RegionMap {
+    pub fn new(src: &RgbImage, metric: DistanceMetric, tolerance: f32, connectivity: Connectivity) -> Self {
+        let (width, height) = src.dimensions();
+        let position = |x: u32, y: u32| y as usize * width as usize + x as usize;
+        let mut labels = vec![0; width as usize * height as usize];
+        let mut visited = vec![false; labels.len()];
+        let mut regions = vec![];
+        let mut stack = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                if visited[position(x, y)] {
+                    continue;
+                }
+                let label = regions.len();
+                let color = *src.get_pixel(x, y);
+                let (mut area, mut sum, mut positions) = (0u32, [0u64; 3], (0u64, 0u64));
+                let (mut left, mut top, mut right, mut bottom) = (x, y, x, y);
+                visited[position(x, y)] = true;
+                stack.push((x, y));
+                while let Some((px, py)) = stack.pop() {
+                    labels[position(px, py)] = label;
+                    let pixel = src.get_pixel(px, py);
+                    for c in 0..3 {
+                        sum[c] += pixel[c] as u64;
+                    }
+                    area += 1;
+                    positions.0 += px as u64;
+                    positions.1 += py as u64;
+                    left = left.min(px);
+                    right = right.max(px);
+                    top = top.min(py);
+                    bottom = bottom.max(py);
+                    for (dx, dy) in connectivity.neighbours() {
+                        let (nx, ny) = (px as i64 + dx, py as i64 + dy);
+                        if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                            continue;
+                        }
+                        let (nx, ny) = (nx as u32, ny as u32);
+                        if !visited[position(nx, ny)] && metric.within(src.get_pixel(nx, ny), &color, tolerance) {
+                            visited[position(nx, ny)] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                let mean = sum.map(|channel| (channel as f64 / area as f64).round() as u8);
+                regions.push(RegionStats {
+                    first: Point::new(x, y),
+                    color,
+                    mean: Rgb(mean),
+                    area,
+                    bbox: Rect::at(left as i32, top as i32).of_size(right - left + 1, bottom - top + 1),
+                    centroid: Point::new((positions.0 as f64 / area as f64) as f32, (positions.1 as f64 / area as f64) as f32),
+                    perimeter: 0,
+                });
+            }
+        }
+
+        // every edge of a pixel that is on the border, or next to another region
+        for y in 0..height {
+            for x in 0..width {
+                let label = labels[position(x, y)];
+                let outside = EDGES
+                    .iter()
+                    .map(|(dx, dy)| (x as i64 + dx, y as i64 + dy))
+                    .filter(|&(nx, ny)| {
+                        nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64
+                            || labels[position(nx as u32, ny as u32)] != label
+                    })
+                    .count();
+                regions[label].perimeter += outside as u32;
+            }
+        }
+        Self { width, height, labels, regions }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn regions(&self) -> &[RegionStats] {
+        &self.regions
+    }
+
+    pub fn label(&self, x: u32, y: u32) -> usize {
+        self.labels[y as usize * self.width as usize + x as usize]
+    }
+
+    // The map with every region smaller than min_area merged into one of its neighbours,
+    // the smallest first, until all are large enough or there is only one left. A merged region
+    // keeps the first pixel and the color of the neighbour, and the labels stay in the order
+    // of the first pixels.
+    pub fn merged(&self, min_area: u32, by: MergeBy, metric: DistanceMetric) -> RegionMap {
+        let mut borders = self.borders();
+        let mut regions = self.regions.clone();
+        let mut sums: Vec<[f64; 3]> = regions.iter().map(|region| region.mean.0.map(|c| c as f64 * region.area as f64)).collect();
+        // the region that each one went into, itself while it is still there
+        let mut into: Vec<usize> = (0..regions.len()).collect();
+        let mut small: BinaryHeap<Reverse<(u32, usize)>> = regions
+            .iter()
+            .enumerate()
+            .filter(|(_, region)| region.area < min_area)
+            .map(|(label, region)| Reverse((region.area, label)))
+            .collect();
+        while let Some(Reverse((area, label))) = small.pop() {
+            // the region grew or went into another one since it was queued
+            if into[label] != label || regions[label].area != area {
+                continue;
+            }
+            let color = |other: usize| metric.distance(&regions[label].mean, &regions[other].mean);
+            let target = borders[label].iter().min_by(|(a, a_border), (b, b_border)| {
+                let by_color = color(**a).partial_cmp(&color(**b)).unwrap_or(Ordering::Equal);
+                let by_border = b_border.cmp(a_border);
+                match by {
+                    MergeBy::Color => by_color.then(by_border),
+                    MergeBy::Border => by_border.then(by_color),
+                }
+                .then(a.cmp(b))
+            });
+            let Some((&target, &shared)) = target else { continue };
+
+            for (neighbour, border) in std::mem::take(&mut borders[label]) {
+                borders[neighbour].remove(&label);
+                if neighbour != target {
+                    *borders[neighbour].entry(target).or_insert(0) += border;
+                    *borders[target].entry(neighbour).or_insert(0) += border;
+                }
+            }
+            into[label] = target;
+            let (absorbed, kept) = (regions[label].clone(), &mut regions[target]);
+            let area = (kept.area + absorbed.area) as f32;
+            kept.centroid = Point::new(
+                (kept.centroid.x * kept.area as f32 + absorbed.centroid.x * absorbed.area as f32) / area,
+                (kept.centroid.y * kept.area as f32 + absorbed.centroid.y * absorbed.area as f32) / area,
+            );
+            kept.area += absorbed.area;
+            kept.perimeter = kept.perimeter + absorbed.perimeter - 2 * shared;
+            let (left, top) = (kept.bbox.left().min(absorbed.bbox.left()), kept.bbox.top().min(absorbed.bbox.top()));
+            let (right, bottom) = (kept.bbox.right().max(absorbed.bbox.right()), kept.bbox.bottom().max(absorbed.bbox.bottom()));
+            kept.bbox = Rect::at(left, top).of_size((right - left + 1) as u32, (bottom - top + 1) as u32);
+            let absorbed_sum = sums[label];
+            for (c, sum) in sums[target].iter_mut().enumerate() {
+                *sum += absorbed_sum[c];
+                kept.mean.0[c] = (*sum / kept.area as f64).round() as u8;
+            }
+            if kept.area < min_area {
+                small.push(Reverse((kept.area, target)));
+            }
+        }
+
+        // the regions that are left get new labels, the merged ones the label of the region they ended up in
+        let mut relabeled = vec![0; regions.len()];
+        let mut kept = vec![];
+        for label in 0..regions.len() {
+            if into[label] == label {
+                relabeled[label] = kept.len();
+                kept.push(regions[label].clone());
+            }
+        }
+        for label in 0..regions.len() {
+            let mut root = label;
+            while into[root] != root {
+                root = into[root];
+            }
+            relabeled[label] = relabeled[root];
+        }
+        let labels = self.labels.iter().map(|label| relabeled[*label]).collect();
+        Self { width: self.width, height: self.height, labels, regions: kept }
+    }
+
+    // the number of pixel edges that every region shares with each of its neighbours
+    fn borders(&self) -> Vec<HashMap<usize, u32>> {
+        let mut borders = vec![HashMap::new(); self.regions.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let label = self.label(x, y);
+                let right = (x + 1 < self.width).then(|| self.label(x + 1, y));
+                let below = (y + 1 < self.height).then(|| self.label(x, y + 1));
+                for other in right.into_iter().chain(below).filter(|other| *other != label) {
+                    *borders[label].entry(other).or_insert(0) += 1;
+                    *borders[other].entry(label).or_insert(0) += 1;
+                }
+            }
+        }
+        borders
+    }
+
+    pub fn graph(&self, metric: DistanceMetric) -> RegionGraph {
+        let neighbours = self
+            .borders()
+            .into_iter()
+            .enumerate()
+            .map(|(label, borders)| {
+                let mut neighbours: Vec<Adjacency> = borders
+                    .into_iter()
+                    .map(|(neighbour, border)| Adjacency {
+                        neighbour,
+                        border,
+                        distance: metric.distance(&self.regions[label].mean, &self.regions[neighbour].mean),
+                    })
+                    .collect();
+                neighbours.sort_by_key(|adjacency| adjacency.neighbour);
+                neighbours
+            })
+            .collect();
+        RegionGraph { neighbours }
+    }
+
+    // the pixels of every region, by label
+    pub fn points(&self) -> Vec<Vec<Point<u32>>> {
+        let mut points: Vec<Vec<Point<u32>>> = self.regions.iter().map(|region| Vec::with_capacity(region.area as usize)).collect();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                points[self.label(x, y)].push(Point::new(x, y));
+            }
+        }
+        points
+    }
+}
+
+// An edge of the region graph: the neighbour, the number of pixel edges the two regions
+// share, and the distance between their mean colors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjacency {
+    pub neighbour: usize,
+    pub border: u32,
+    pub distance: f32,
+}
+
+// Which regions of a RegionMap touch, by label. Regions only touch when they share an edge,
+// also with 8 neighbours.
+#[derive(Debug, Clone, Default)]
+pub struct RegionGraph {
+    // the neighbours of every region, by label
+    neighbours: Vec<Vec<Adjacency>>,
+}
+
+impl RegionGraph {
+    pub fn len(&self) -> usize {
+        self.neighbours.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.neighbours.is_empty()
+    }
+
+    // the neighbours of a region, by label
+    pub fn neighbours(&self, label: usize) -> &[Adjacency] {
+        &self.neighbours[label]
+    }
+
+    // the edge between two regions, when they touch
+    pub fn adjacency(&self, a: usize, b: usize) -> Option<&Adjacency> {
+        self.neighbours[a]
+            .binary_search_by_key(&b, |adjacency| adjacency.neighbour)
+            .ok()
+            .map(|i| &self.neighbours[a][i])
+    }
+
+    // every edge once, from the region with the lower label
+    pub fn edges(&self) -> impl Iterator<Item = (usize, &Adjacency)> {
+        self.neighbours
+            .iter()
+            .enumerate()
+            .flat_map(|(label, neighbours)| neighbours.iter().filter(move |adjacency| adjacency.neighbour > label).map(move |adjacency| (label, adjacency)))
+    }
+}
+
+// The regions of an image with their graph, as a sample fill with the same metric,
+// tolerance and connectivity sees them.
+#[derive(Debug, Clone)]
+pub struct Regions {
+    pub map: RegionMap,
+    pub graph: RegionGraph,
+}
+
+impl Regions {
+    pub fn new(src: &RgbImage, metric: DistanceMetric, tolerance: f32, connectivity: Connectivity) -> Self {
+        let map = RegionMap::new(src, metric, tolerance, connectivity);
+        let graph = map.graph(metric);
+        Self { map, graph }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels_every_pixel() {
+        // a black column on the left, and a grey square in the bottom right corner
+        let img = RgbImage::from_fn(4, 4, |x, y| match (x, y) {
+            (0, _) => Rgb([0, 0, 0]),
+            (2.., 2..) => Rgb([100, 100, 100]),
+            _ => Rgb([200, 200, 200]),
+        });
+        let map = RegionMap::new(&img, DistanceMetric::Rgb, 4.0, Connectivity::Four);
+        let regions = map.regions();
+        assert_eq!(3, regions.len());
+        assert_eq!((Rgb([0, 0, 0]), 4, 10), (regions[0].color, regions[0].area, regions[0].perimeter));
+        assert_eq!((Point::new(1, 0), 8, Rect::at(1, 0).of_size(3, 4)), (regions[1].first, regions[1].area, regions[1].bbox));
+        assert_eq!(Point::new(2.5, 2.5), regions[2].centroid);
+        assert_eq!((Rgb([100, 100, 100]), 4, 8), (regions[2].mean, regions[2].area, regions[2].perimeter));
+        assert_eq!((0, 1, 2), (map.label(0, 3), map.label(3, 0), map.label(3, 3)));
+        assert_eq!(vec![4, 8, 4], map.points().iter().map(|points| points.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn connects_corners_with_eight_neighbours() {
+        let img = RgbImage::from_fn(3, 3, |x, y| if x == y { Rgb([50, 50, 50]) } else { Rgb([90, 20, 20]) });
+        assert_eq!(5, RegionMap::new(&img, DistanceMetric::Rgb, 4.0, Connectivity::Four).regions().len());
+        let map = RegionMap::new(&img, DistanceMetric::Rgb, 4.0, Connectivity::Eight);
+        assert_eq!(vec![3, 6], map.regions().iter().map(|region| region.area).collect::<Vec<_>>());
+        assert_eq!(Rgb([50, 50, 50]), map.regions()[0].mean);
+    }
+
+    #[test]
+    fn graph_of_touching_regions() {
+        // three stripes, and a square in the middle one that only touches it
+        let img = RgbImage::from_fn(8, 6, |x, y| match (x, y) {
+            (3..=4, 2..=3) => Rgb([0, 0, 250]),
+            (0..=1, _) => Rgb([200, 0, 0]),
+            (2..=5, _) => Rgb([0, 200, 0]),
+            _ => Rgb([100, 0, 0]),
+        });
+        let graph = Regions::new(&img, DistanceMetric::Rgb, 4.0, Connectivity::Four).graph;
+        assert_eq!(4, graph.len());
+        assert_eq!(vec![1], graph.neighbours(0).iter().map(|adjacency| adjacency.neighbour).collect::<Vec<_>>());
+        assert_eq!(vec![0, 2, 3], graph.neighbours(1).iter().map(|adjacency| adjacency.neighbour).collect::<Vec<_>>());
+        assert_eq!(Some((6, 283.0)), graph.adjacency(0, 1).map(|adjacency| (adjacency.border, adjacency.distance.round())));
+        assert_eq!(Some(8), graph.adjacency(3, 1).map(|adjacency| adjacency.border));
+        assert!(graph.adjacency(0, 2).is_none());
+        assert_eq!(vec![(0, 1), (1, 2), (1, 3)], graph.edges().map(|(label, adjacency)| (label, adjacency.neighbour)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn merges_small_regions() {
+        // a dark pixel in the light half, with three light neighbours and one dark one
+        let img = RgbImage::from_fn(6, 4, |x, y| match (x, y) {
+            (2, 1) => Rgb([60, 50, 50]),
+            (0..=2, _) => Rgb([200, 200, 200]),
+            _ => Rgb([50, 50, 50]),
+        });
+        let map = RegionMap::new(&img, DistanceMetric::Rgb, 4.0, Connectivity::Four);
+        assert_eq!(3, map.merged(1, MergeBy::Color, DistanceMetric::Rgb).regions().len());
+
+        let by_color = map.merged(2, MergeBy::Color, DistanceMetric::Rgb);
+        assert_eq!(2, by_color.regions().len());
+        assert_eq!(1, by_color.label(2, 1));
+        let dark = &by_color.regions()[1];
+        assert_eq!((Rgb([50, 50, 50]), Rgb([51, 50, 50]), 13, 16), (dark.color, dark.mean, dark.area, dark.perimeter));
+        assert_eq!(Rect::at(2, 0).of_size(4, 4), dark.bbox);
+        assert!((dark.centroid.x - 50.0 / 13.0).abs() < 1e-5 && (dark.centroid.y - 19.0 / 13.0).abs() < 1e-5);
+
+        let by_border = map.merged(2, MergeBy::Border, DistanceMetric::Rgb);
+        assert_eq!(0, by_border.label(2, 1));
+        assert_eq!((Rgb([200, 200, 200]), 12), (by_border.regions()[0].color, by_border.regions()[0].area));
+
+        let all = map.merged(100, MergeBy::Color, DistanceMetric::Rgb);
+        assert_eq!(vec![24], all.regions().iter().map(|region| region.area).collect::<Vec<_>>());
+        assert!(all.labels.iter().all(|label| *label == 0));
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::Arc;
+
+use image::RgbImage;
+
+use crate::catalog::SampleEntry;
+use crate::descriptor::TextureDescriptor;
+
+pub struct ColorSample {
+    pub id: String,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub image: RgbImage,
+    // the tags and the descriptor of its catalog entry
+    pub tags: Vec<String>,
+    pub descriptor: Option<TextureDescriptor>,
+}
+
+impl ColorSample {
+    // the sample for a catalog entry, with the image loaded from its file
+    pub fn new(entry: &SampleEntry, image: RgbImage) -> Self {
+        let [r, g, b] = entry.mean;
+        Self { id: entry.id.clone(), r, g, b, image, tags: entry.tags.clone(), descriptor: entry.descriptor.clone() }
+    }
+
+    // the memory the sample takes, in bytes
+    pub fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.id.len() + self.image.as_raw().len() + self.tags.iter().map(String::len).sum::<usize>()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+// 256 MiB, several hundred samples of the library
+pub const DEFAULT_BUDGET: usize = 256 << 20;
+
+// Decoded samples, shared as Arc handles. When the samples take more than the memory
+// budget, the least recently used ones are dropped from the store; handles that are
+// still in use elsewhere stay valid.
+pub struct SampleStore {
+    samples: HashMap<String, Cached>,
+    // ids by the tick they were last used at, oldest first
+    recent: BTreeMap<u64, String>,
+    tick: u64,
+    budget: usize,
+    used: usize,
+    stats: CacheStats,
+}
+
+struct Cached {
+    sample: Arc<ColorSample>,
+    last_used: u64,
+}
+
+impl Default for SampleStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUDGET)
+    }
+}
+
+impl SampleStore {
+    // budget is in bytes
+    pub fn new(budget: usize) -> Self {
+        Self { samples: HashMap::new(), recent: BTreeMap::new(), tick: 0, budget, used: 0, stats: CacheStats::default() }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.samples.contains_key(id)
+    }
+
+    // counts as a hit or a miss, and as a use of the sample
+    pub fn get(&mut self, id: &str) -> Option<Arc<ColorSample>> {
+        self.tick += 1;
+        match self.samples.get_mut(id) {
+            Some(cached) => {
+                self.stats.hits += 1;
+                self.recent.remove(&cached.last_used);
+                self.recent.insert(self.tick, id.to_owned());
+                cached.last_used = self.tick;
+                Some(cached.sample.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Adds (or replaces) a sample, and evicts samples until the store fits its budget again.
+    // The new sample itself is never evicted, even when it is larger than the budget.
+    pub fn insert(&mut self, sample: ColorSample) -> Arc<ColorSample> {
+        let id = sample.id.clone();
+        self.remove(&id);
+        self.tick += 1;
+        let sample = Arc::new(sample);
+        self.used += sample.size();
+        self.recent.insert(self.tick, id.clone());
+        self.samples.insert(id.clone(), Cached { sample: sample.clone(), last_used: self.tick });
+        self.evict(Some(&id));
+        sample
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Arc<ColorSample>> {
+        let cached = self.samples.remove(id)?;
+        self.recent.remove(&cached.last_used);
+        self.used -= cached.sample.size();
+        Some(cached.sample)
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.recent.clear();
+        self.used = 0;
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict(None);
+    }
+
+    // drops the least recently used samples, except keep, until the store fits the budget
+    fn evict(&mut self, keep: Option<&str>) {
+        while self.used > self.budget {
+            match self.recent.values().next().cloned() {
+                Some(oldest) if Some(oldest.as_str()) != keep => {
+                    self.remove(&oldest);
+                    self.stats.evictions += 1;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    // bytes taken by the samples in the store
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgb;
+
+    use super::*;
+
+    fn sample(id: &str) -> ColorSample {
+        ColorSample::new(&SampleEntry::new(id, [1, 2, 3], [10, 10]), RgbImage::from_pixel(10, 10, Rgb([1, 2, 3])))
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let size = sample("a").size();
+        let mut store = SampleStore::new(size * 2);
+        store.insert(sample("a"));
+        let b = store.insert(sample("b"));
+        assert!(store.get("a").is_some());
+        store.insert(sample("c"));
+
+        assert!(store.contains("a"));
+        assert!(!store.contains("b"));
+        assert!(store.contains("c"));
+        assert_eq!(size * 2, store.used());
+        // the handle outlives the eviction
+        assert_eq!("b", b.id);
+        assert!(store.get("b").is_none());
+        assert_eq!(CacheStats { hits: 1, misses: 1, evictions: 1 }, store.stats());
+
+        store.set_budget(size);
+        assert_eq!(vec![false, true], vec![store.contains("a"), store.contains("c")]);
+    }
+
+    #[test]
+    fn keeps_a_sample_larger_than_the_budget() {
+        let mut store = SampleStore::new(1);
+        store.insert(sample("a"));
+        store.insert(sample("b"));
+        assert_eq!(1, store.len());
+        assert!(store.contains("b"));
+        store.insert(sample("b"));
+        assert_eq!(sample("b").size(), store.used());
+    }
+}
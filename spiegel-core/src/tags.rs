@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::{Catalog, SampleEntry};
+use crate::color::DistanceMetric;
+use crate::pack::PackIndex;
+
+// Tags are free text. The metadata of a sample is in key:value tags with these keys,
+// like material:wood or scale:fine.
+pub const MATERIAL: &str = "material";
+pub const BRIGHTNESS: &str = "brightness";
+pub const SCALE: &str = "scale";
+pub const SOURCE: &str = "source";
+
+// the key of a key:value tag
+pub fn key(tag: &str) -> Option<&str> {
+    tag.split_once(':').map(|(key, _)| key)
+}
+
+// A tag in a rule matches the same tag on a sample, and a rule tag without a key also
+// matches the value of a key:value tag, so that wood matches material:wood.
+pub fn matches(rule_tag: &str, tag: &str) -> bool {
+    rule_tag == tag || (!rule_tag.contains(':') && tag.split_once(':').is_some_and(|(_, value)| value == rule_tag))
+}
+
+// Narrows the samples to choose from, for all colors or for the colors near one.
+// A sample is allowed when it has one of the included tags (or nothing is included),
+// and none of the excluded tags.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TagRule {
+    // the colors the rule is for, all colors when there is none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub near: Option<ColorRange>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+// the colors within distance of color, in the metric of the sample fill
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorRange {
+    pub color: [u8; 3],
+    pub distance: f32,
+}
+
+impl TagRule {
+    pub fn applies_to(&self, color: &Rgb<u8>, metric: DistanceMetric) -> bool {
+        match &self.near {
+            Some(range) => metric.distance(color, &Rgb(range.color)) <= range.distance,
+            None => true,
+        }
+    }
+
+    pub fn allows(&self, entry: &SampleEntry) -> bool {
+        let has = |rule_tag: &String| entry.tags.iter().any(|tag| matches(rule_tag, tag));
+        (self.include.is_empty() || self.include.iter().any(has)) && !self.exclude.iter().any(has)
+    }
+}
+
+// Nearest sample lookups that follow the tag rules. Every combination of rules that applies
+// to a color gets its own index, built the first time it is needed. When the rules leave no
+// sample for a color, the color alone decides.
+pub struct TagIndex<'a> {
+    catalog: &'a Catalog,
+    metric: DistanceMetric,
+    rules: &'a [TagRule],
+    all: PackIndex,
+    // by the positions of the rules that apply, None when they allow no sample
+    filtered: HashMap<Vec<usize>, Option<PackIndex>>,
+}
+
+impl<'a> TagIndex<'a> {
+    pub fn new(catalog: &'a Catalog, metric: DistanceMetric, rules: &'a [TagRule]) -> Self {
+        Self { catalog, metric, rules, all: catalog.pack_index(metric), filtered: HashMap::new() }
+    }
+
+    // the position in the catalog of the closest allowed sample, with its weighted distance
+    pub fn nearest(&mut self, color: &Rgb<u8>) -> Option<(usize, f32)> {
+        self.index(color).nearest(color)
+    }
+
+    // the k closest allowed samples, closest first
+    pub fn k_nearest(&mut self, color: &Rgb<u8>, k: usize) -> Vec<(usize, f32)> {
+        self.index(color).k_nearest(color, k)
+    }
+
+    // the index over the samples that the rules for color allow
+    fn index(&mut self, color: &Rgb<u8>) -> &PackIndex {
+        let applying: Vec<usize> = (0..self.rules.len()).filter(|i| self.rules[*i].applies_to(color, self.metric)).collect();
+        if applying.is_empty() {
+            return &self.all;
+        }
+        let (catalog, metric, rules) = (self.catalog, self.metric, self.rules);
+        let index = self.filtered.entry(applying).or_insert_with_key(|applying| {
+            let allowed = |entry: &SampleEntry| applying.iter().all(|i| rules[*i].allows(entry));
+            catalog.samples.iter().any(allowed).then(|| PackIndex::filtered(catalog, metric, allowed))
+        });
+        index.as_ref().unwrap_or(&self.all)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(id: &str, mean: [u8; 3], tags: &[&str]) -> SampleEntry {
+        SampleEntry { tags: tags.iter().map(|tag| tag.to_string()).collect(), ..SampleEntry::new(id, mean, [1, 1]) }
+    }
+
+    #[test]
+    fn rules_match_tags_and_values() {
+        let rule = TagRule { include: vec!["wood".to_owned()], exclude: vec!["scale:fine".to_owned()], ..TagRule::default() };
+        assert!(rule.allows(&entry("oak", [0, 0, 0], &["material:wood", "scale:coarse"])));
+        assert!(!rule.allows(&entry("birch", [0, 0, 0], &["material:wood", "scale:fine"])));
+        assert!(!rule.allows(&entry("slate", [0, 0, 0], &["material:stone"])));
+        assert!(!matches("wood", "material:woodland"));
+    }
+
+    #[test]
+    fn rules_narrow_the_colors_they_apply_to() {
+        let mut catalog = Catalog::default();
+        catalog.samples.push(entry("brown", [120, 80, 40], &["material:stone"]));
+        catalog.samples.push(entry("oak", [140, 90, 40], &["material:wood"]));
+        catalog.samples.push(entry("stripes", [30, 30, 200], &["pattern:stripes"]));
+        let rules = vec![
+            TagRule {
+                near: Some(ColorRange { color: [120, 80, 40], distance: 40.0 }),
+                include: vec!["wood".to_owned()],
+                ..TagRule::default()
+            },
+            TagRule { exclude: vec!["stripes".to_owned()], ..TagRule::default() },
+        ];
+        let mut index = TagIndex::new(&catalog, DistanceMetric::Rgb, &rules);
+
+        // only wood for brown
+        assert_eq!(Some(1), index.nearest(&Rgb([120, 80, 40])).map(|(i, _)| i));
+        // never stripes, the brown rule does not apply to blue
+        assert_eq!(Some(0), index.nearest(&Rgb([30, 30, 200])).map(|(i, _)| i));
+
+        // rules that allow nothing are ignored
+        let nothing = vec![TagRule { include: vec!["fabric".to_owned()], ..TagRule::default() }];
+        assert_eq!(Some(2), TagIndex::new(&catalog, DistanceMetric::Rgb, &nothing).nearest(&Rgb([30, 30, 200])).map(|(i, _)| i));
+    }
+}
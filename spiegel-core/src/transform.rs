@@ -0,0 +1,385 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use image::{Rgb, RgbImage};
+use imageproc::point::Point;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::{Catalog, SampleEntry};
+use crate::descriptor::TextureDescriptor;
+use crate::filter::SampleFill;
+use crate::regions::{RegionStats, Regions};
+use crate::samples::ColorSample;
+use crate::tags::TagIndex;
+
+// with texture matching, the sample is chosen among at least this many closest in color
+const CANDIDATES: usize = 16;
+
+// Where the texture of a region starts: at the top left corner of the image for all regions,
+// so that they share one grid of tiles, or at the top left corner of the region, or
+// centered on it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    #[default]
+    Grid,
+    Bbox,
+    Centroid,
+}
+
+// How a texture continues past its edges: it starts over, it continues mirrored, so that
+// its edges meet themselves, or its edge pixels go on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tiling {
+    #[default]
+    Wrap,
+    Mirror,
+    Clamp,
+}
+
+impl Tiling {
+    // the position within a side of length side for a position anywhere
+    fn position(&self, i: i64, side: u32) -> u32 {
+        let side = side as i64;
+        let i = match self {
+            Tiling::Wrap => i.rem_euclid(side),
+            Tiling::Mirror => {
+                let i = i.rem_euclid(2 * side);
+                if i < side { i } else { 2 * side - 1 - i }
+            }
+            Tiling::Clamp => i.clamp(0, side - 1),
+        };
+        i as u32
+    }
+}
+
+// How the sample is laid on a region. The offset, the turn and the mirroring are random per
+// region, from the seed of the sample fill. Quarter turns change the direction of a texture,
+// which texture matching looks at, half turns keep it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Placement {
+    #[serde(default)]
+    pub anchor: Anchor,
+    // shifts the texture by up to its size
+    #[serde(default)]
+    pub offset: bool,
+    // turns the texture by a quarter, half or three quarter turn, or not at all
+    #[serde(default)]
+    pub rotate: bool,
+    // mirrors the texture horizontally
+    #[serde(default)]
+    pub flip: bool,
+    #[serde(default)]
+    pub tiling: Tiling,
+}
+
+// where the sample of a region starts, in the image, and how it is turned
+struct Tile {
+    x: i64,
+    y: i64,
+    quarters: u64,
+    flip: bool,
+    tiling: Tiling,
+}
+
+impl Tile {
+    fn new(region: &RegionStats, sample: &RgbImage, placement: &Placement, seed: u64) -> Self {
+        let (width, height) = sample.dimensions();
+        let (x, y) = match placement.anchor {
+            Anchor::Grid => (0, 0),
+            Anchor::Bbox => (region.bbox.left() as i64, region.bbox.top() as i64),
+            Anchor::Centroid => {
+                let x = (region.centroid.x - width as f32 / 2.0).round();
+                let y = (region.centroid.y - height as f32 / 2.0).round();
+                (x as i64, y as i64)
+            }
+        };
+        // a second number for the region, apart from the one for choosing its sample
+        let random = hash(hash(seed, &region.first), &region.first);
+        let (dx, dy) = if placement.offset { (random % width as u64, (random >> 16) % height as u64) } else { (0, 0) };
+        Self {
+            x: x - dx as i64,
+            y: y - dy as i64,
+            quarters: if placement.rotate { (random >> 32) % 4 } else { 0 },
+            flip: placement.flip && (random >> 34) % 2 == 1,
+            tiling: placement.tiling,
+        }
+    }
+
+    // the pixel of sample at x, y in the image
+    fn pixel(&self, sample: &RgbImage, x: u32, y: u32) -> Rgb<u8> {
+        let (u, v) = (x as i64 - self.x, y as i64 - self.y);
+        let u = if self.flip { -1 - u } else { u };
+        let (u, v) = match self.quarters {
+            0 => (u, v),
+            1 => (v, -1 - u),
+            2 => (-1 - u, -1 - v),
+            _ => (-1 - v, u),
+        };
+        let (width, height) = sample.dimensions();
+        *sample.get_pixel(self.tiling.position(u, width), self.tiling.position(v, height))
+    }
+}
+
+// the catalog entries of the chosen samples, which apply_samples_to_image needs
+pub fn required_samples<'a>(catalog: &'a Catalog, chosen: &[Option<usize>]) -> Vec<&'a SampleEntry> {
+    let mut positions: Vec<usize> = chosen.iter().flatten().copied().collect();
+    positions.sort_unstable();
+    positions.dedup();
+    positions.into_iter().map(|i| &catalog.samples[i]).collect()
+}
+
+// Fills every region with the sample chosen for it, see choose. Samples are looked up by id,
+// regions without a sample are left black. The regions are those that the samples were
+// chosen for.
+pub fn apply_samples_to_image(
+    src: RgbImage,
+    regions: &Regions,
+    chosen: &[Option<usize>],
+    catalog: &Catalog,
+    samples: &HashMap<String, Arc<ColorSample>>,
+    fill: &SampleFill,
+) -> RgbImage {
+    let chosen: Vec<Option<(&Arc<ColorSample>, Tile)>> = chosen
+        .iter()
+        .zip(regions.map.regions())
+        .map(|(i, region)| {
+            let sample = samples.get(&catalog.samples[(*i)?].id)?;
+            Some((sample, Tile::new(region, &sample.image, &fill.placement, fill.seed)))
+        })
+        .collect();
+
+    // the sample of every region tiles it
+    let mut imgbuf = RgbImage::new(src.width(), src.height());
+    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
+        if let Some((sample, tile)) = &chosen[regions.map.label(x, y)] {
+            *pixel = tile.pixel(&sample.image, x, y);
+        }
+    }
+    imgbuf
+}
+
+// The position in the catalog of the sample of every region, by label. The sample for a
+// region is the closest one that the tag rules allow, in color, and in texture when fill has
+// a texture weight. The texture of a region is taken from the original image, before the
+// blur and the quantization, when it has the size of src. With distinct, the regions are
+// matched in the order of their labels, and each one avoids the samples of the neighbours
+// before it.
+pub fn choose(src: &RgbImage, regions: &Regions, original: &RgbImage, catalog: &Catalog, fill: &SampleFill) -> Vec<Option<usize>> {
+    let mut index = TagIndex::new(catalog, fill.metric, &fill.tags);
+    let original = if original.dimensions() == src.dimensions() { original } else { src };
+    // the pixels of every region are only needed for its texture
+    let points = if fill.texture > 0.0 { regions.map.points() } else { vec![] };
+    let mut chosen: Vec<Option<usize>> = Vec::with_capacity(regions.map.regions().len());
+    for (label, region) in regions.map.regions().iter().enumerate() {
+        let points = points.get(label).map_or(&[][..], Vec::as_slice);
+        let taken: Vec<usize> = if fill.distinct {
+            regions.graph.neighbours(label)
+                .iter()
+                .filter(|adjacency| adjacency.neighbour < label)
+                .filter_map(|adjacency| chosen[adjacency.neighbour])
+                .collect()
+        } else {
+            vec![]
+        };
+        chosen.push(get_closest(region, points, original, &mut index, catalog, fill, &taken));
+    }
+    chosen
+}
+
+// The position in the catalog of the sample for a region: the closest in color, or with a
+// texture weight, the one of the closest in color with the smallest color distance plus
+// texture times the texture distance. Samples without a descriptor match any texture.
+// With more than one choice, the sample is drawn from the closest ones, see SampleFill.
+// Taken samples are only used when all the closest ones are taken.
+fn get_closest(
+    region: &RegionStats,
+    points: &[Point<u32>],
+    original: &RgbImage,
+    index: &mut TagIndex,
+    catalog: &Catalog,
+    fill: &SampleFill,
+    taken: &[usize],
+) -> Option<usize> {
+    if fill.texture <= 0.0 && fill.choices <= 1 && taken.is_empty() {
+        return index.nearest(&region.color).map(|(i, _)| i);
+    }
+    let mut candidates = index.k_nearest(&region.color, CANDIDATES.max(fill.choices));
+    if fill.texture > 0.0 {
+        let descriptor = TextureDescriptor::of_points(original, points.iter().map(|point| (point.x, point.y)));
+        for (i, distance) in candidates.iter_mut() {
+            *distance += fill.texture * catalog.samples[*i].descriptor.as_ref().map_or(0.0, |d| d.distance(&descriptor));
+        }
+        candidates.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+    }
+    if candidates.iter().any(|(i, _)| !taken.contains(i)) {
+        candidates.retain(|(i, _)| !taken.contains(i));
+    }
+    let closest = candidates.first()?.1;
+    candidates.truncate(fill.choices.max(1));
+    candidates.retain(|(_, distance)| *distance <= closest + fill.spread);
+
+    // closer samples are more likely, the closest has weight 1
+    let weights: Vec<f32> = candidates.iter().map(|(_, distance)| 1.0 / (1.0 + distance - closest)).collect();
+    let mut pick = random(fill.seed, &region.first) as f32 * weights.iter().sum::<f32>();
+    for ((i, _), weight) in candidates.iter().zip(&weights) {
+        if pick < *weight {
+            return Some(*i);
+        }
+        pick -= weight;
+    }
+    candidates.last().map(|(i, _)| *i)
+}
+
+// A number in [0, 1) for a region, from the seed and its first pixel, so that a region gets
+// the same number however often and in whatever order the regions are matched.
+fn random(seed: u64, point: &Point<u32>) -> f64 {
+    (hash(seed, point) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// splitmix64
+fn hash(seed: u64, point: &Point<u32>) -> u64 {
+    let mut z = seed ^ ((point.x as u64) << 32 | point.y as u64);
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+impl PartialEq for ColorSample {
+    fn eq(&self, other: &Self) -> bool {
+        self.r == other.r && self.g == other.g && self.b == other.b
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::Rgb;
+    use imageproc::rect::Rect;
+
+    use super::*;
+    use crate::regions::Regions;
+
+    #[test]
+    fn texture_picks_smooth_or_busy_samples() {
+        let busy = RgbImage::from_fn(8, 8, |x, _| if x % 2 == 0 { Rgb([60, 60, 60]) } else { Rgb([140, 140, 140]) });
+        let smooth = RgbImage::from_pixel(8, 8, Rgb([104, 100, 100]));
+        let mut catalog = Catalog::default();
+        for (id, image) in [("smooth", &smooth), ("busy", &busy)] {
+            let descriptor = TextureDescriptor::of_image(image);
+            catalog.samples.push(SampleEntry { descriptor: Some(descriptor), ..SampleEntry::new(id, [100, 100, 100], [8, 8]) });
+        }
+        catalog.samples[0].mean = [104, 100, 100];
+        let samples: HashMap<String, Arc<ColorSample>> = catalog.samples.iter()
+            .zip([smooth, busy.clone()])
+            .map(|(entry, image)| (entry.id.clone(), Arc::new(ColorSample::new(entry, image))))
+            .collect();
+
+        let prepared = RgbImage::from_pixel(8, 8, Rgb([102, 100, 100]));
+        let fill = SampleFill::default();
+        let regions = Regions::new(&prepared, fill.metric, fill.tolerance, fill.connectivity);
+        // on color alone both are as close, the first one wins
+        let chosen = choose(&prepared, &regions, &busy, &catalog, &fill);
+        let out = apply_samples_to_image(prepared.clone(), &regions, &chosen, &catalog, &samples, &fill);
+        assert_eq!(&Rgb([104, 100, 100]), out.get_pixel(0, 0));
+
+        let fill = SampleFill { texture: 1.0, ..fill };
+        let chosen = choose(&prepared, &regions, &busy, &catalog, &fill);
+        let out = apply_samples_to_image(prepared.clone(), &regions, &chosen, &catalog, &samples, &fill);
+        assert_eq!(busy, out);
+        let ids: Vec<&str> = required_samples(&catalog, &chosen).iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(vec!["busy"], ids);
+    }
+
+    #[test]
+    fn choices_follow_the_seed() {
+        let mut catalog = Catalog::default();
+        for (id, mean) in [("grey", [100, 100, 100]), ("lighter", [103, 100, 100]), ("darker", [97, 100, 100]), ("red", [200, 0, 0])] {
+            catalog.samples.push(SampleEntry::new(id, mean, [1, 1]));
+        }
+        let original = RgbImage::new(16, 16);
+        let fill = SampleFill { choices: 4, seed: 7, ..Default::default() };
+        let picks = |fill: &SampleFill| -> Vec<usize> {
+            let mut index = TagIndex::new(&catalog, fill.metric, &fill.tags);
+            (0..16 * 16)
+                .map(|i| {
+                    let (x, y) = (i % 16, i / 16);
+                    let color = Rgb([100, 100, 100]);
+                    let bbox = Rect::at(x as i32, y as i32).of_size(1, 1);
+                    let centroid = Point::new(x as f32, y as f32);
+                    RegionStats { first: Point::new(x, y), color, mean: color, area: 1, bbox, centroid, perimeter: 4 }
+                })
+                .map(|region| get_closest(&region, &[region.first], &original, &mut index, &catalog, fill, &[]).unwrap())
+                .collect()
+        };
+
+        let first = picks(&fill);
+        assert_eq!(first, picks(&fill));
+        assert_ne!(first, picks(&SampleFill { seed: 8, ..fill.clone() }));
+        // the close ones all get picked, the closest most often, red is too far
+        let count = |i: usize| first.iter().filter(|pick| **pick == i).count();
+        assert!(count(1) > 0 && count(2) > 0);
+        assert!(count(0) > count(1) && count(0) > count(2));
+        assert_eq!(0, count(3));
+
+        assert!(picks(&SampleFill { choices: 1, ..fill.clone() }).iter().all(|pick| *pick == 0));
+        assert!(picks(&SampleFill { spread: 0.0, ..fill.clone() }).iter().all(|pick| *pick == 0));
+    }
+
+    #[test]
+    fn distinct_neighbours_get_different_samples() {
+        let mut catalog = Catalog::default();
+        catalog.samples.push(SampleEntry::new("close", [105, 100, 100], [1, 1]));
+        catalog.samples.push(SampleEntry::new("further", [120, 100, 100], [1, 1]));
+        // two regions that are both closest to the same sample, and a third that only touches the second
+        let prepared = RgbImage::from_fn(6, 1, |x, _| [Rgb([100, 100, 100]), Rgb([110, 100, 100]), Rgb([100, 100, 100])][x as usize / 2]);
+        let fill = SampleFill::default();
+        let regions = Regions::new(&prepared, fill.metric, fill.tolerance, fill.connectivity);
+        assert_eq!(vec![Some(0), Some(0), Some(0)], choose(&prepared, &regions, &prepared, &catalog, &fill));
+
+        let fill = SampleFill { distinct: true, ..fill };
+        let chosen = choose(&prepared, &regions, &prepared, &catalog, &fill);
+        assert_eq!(vec![Some(0), Some(1), Some(0)], chosen);
+        assert_eq!(2, required_samples(&catalog, &chosen).len());
+    }
+
+    #[test]
+    fn tiles_start_at_the_region() {
+        let sample = RgbImage::from_fn(2, 2, |x, y| Rgb([x as u8, y as u8, 0]));
+        let (a, b, c) = (Rgb([0, 0, 0]), Rgb([1, 0, 0]), Rgb([0, 1, 0]));
+        let region = RegionStats {
+            first: Point::new(5, 3),
+            color: a,
+            mean: a,
+            area: 4,
+            bbox: Rect::at(5, 3).of_size(2, 2),
+            centroid: Point::new(5.5, 3.5),
+            perimeter: 8,
+        };
+        let tile = |placement: Placement| Tile::new(&region, &sample, &placement, 0);
+
+        // one grid for all regions, or starting at the region
+        assert_eq!(Rgb([1, 1, 0]), tile(Placement::default()).pixel(&sample, 5, 3));
+        assert_eq!(a, tile(Placement { anchor: Anchor::Bbox, ..Placement::default() }).pixel(&sample, 5, 3));
+        assert_eq!(a, tile(Placement { anchor: Anchor::Centroid, ..Placement::default() }).pixel(&sample, 5, 3));
+
+        // a quarter turn clockwise, and mirrored
+        let turned = Tile { x: 0, y: 0, quarters: 1, flip: false, tiling: Tiling::Wrap };
+        assert_eq!((c, a), (turned.pixel(&sample, 0, 0), turned.pixel(&sample, 1, 0)));
+        let mirrored = Tile { x: 0, y: 0, quarters: 0, flip: true, tiling: Tiling::Wrap };
+        assert_eq!((b, a), (mirrored.pixel(&sample, 0, 0), mirrored.pixel(&sample, 1, 0)));
+
+        let offset = tile(Placement { anchor: Anchor::Bbox, offset: true, ..Placement::default() });
+        assert!((4..=5).contains(&offset.x) && (2..=3).contains(&offset.y));
+    }
+
+    #[test]
+    fn tiling_modes() {
+        let positions = |tiling: Tiling| (-4..7).map(|i| tiling.position(i, 3)).collect::<Vec<_>>();
+        assert_eq!(vec![2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0], positions(Tiling::Wrap));
+        assert_eq!(vec![2, 2, 1, 0, 0, 1, 2, 2, 1, 0, 0], positions(Tiling::Mirror));
+        assert_eq!(vec![0, 0, 0, 0, 0, 1, 2, 2, 2, 2, 2], positions(Tiling::Clamp));
+    }
+}
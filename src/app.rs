@@ -1,36 +1,200 @@
+use std::rc::Rc;
+
 use gloo_utils::document;
 use image::RgbImage;
-use wasm_bindgen::{Clamped, JsCast};
-use wasm_bindgen::prelude::*;
-use web_sys::{DragEvent, HtmlImageElement};
-use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, ImageData};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{DragEvent, File, HtmlImageElement, HtmlInputElement};
 use web_sys::Url;
-use yew::{Component, Context, html, Html};
+use yew::events::Event;
+use yew::{Component, Context, html, Html, TargetCast};
+
+use spiegel_core::catalog::SampleEntry;
+use spiegel_core::filter::{FilterContext, FilterRegistry};
+use spiegel_core::library;
+use spiegel_core::pack::{PackInfo, PackList, PackWeight};
+use spiegel_core::pipeline::Pipeline;
+use spiegel_core::provider::{CombinedProvider, MemoryProvider, ProceduralProvider, SampleProvider};
+use spiegel_core::samples::{ColorSample, SampleStore};
+use spiegel_core::tags;
+
+use crate::http::{self, HttpProvider};
+use crate::interop;
+use crate::interop::get_canvas;
 
-use crate::{samples, transform};
-use crate::samples::{ColorSample, Samples};
+// where packs.json and the sample packs are served
+const STATIC_URL: &str = "/static";
+// the pack that is used when there is no packs.json
+const DEFAULT_PACK: &str = "samples";
+// textures of the user are scaled down to the size of the library samples
+const TEXTURE_SIZE: u32 = 256;
 
 pub enum Msg {
     Dropped(DragEvent),
     Dragged(DragEvent),
     ImageLoaded,
+    PacksReady(PackList),
+    PackOpened(String, Rc<dyn SampleProvider>),
+    PackToggled(String, bool),
+    PackWeighted(String, f32),
+    // the run that loaded them, the sample fill they are for with its image and context,
+    // and the samples
+    SamplesLoaded(u64, usize, RgbImage, FilterContext, Vec<ColorSample>),
+    TexturesDropped(DragEvent),
+    // the file name and the decoded texture
+    TextureLoaded(String, Result<RgbImage, JsValue>),
+    OnlyTextures(bool),
 }
 
 pub struct DropPhoto {
-    samples: Samples
+    samples: SampleStore,
+    pipeline: Pipeline,
+    filters: FilterRegistry,
+    packs: Vec<Pack>,
+    // the active packs combined, once all of them are opened
+    library: Option<Rc<dyn SampleProvider>>,
+    // textures that the user dropped, used next to the library
+    textures: MemoryProvider,
+    // use the textures of the user instead of the library
+    only_textures: bool,
+    // a photo is loaded, so it is transformed again when the samples change,
+    // or once they are ready
+    has_photo: bool,
+    // counts the transformations, only the result of the last one is drawn
+    generation: u64,
+}
+
+// a sample pack in the selector
+struct Pack {
+    info: PackInfo,
+    weight: f32,
+    active: bool,
+    // the pack is opened when it is first activated
+    provider: Option<Rc<dyn SampleProvider>>,
+    opening: bool,
+}
+
+impl DropPhoto {
+    // the samples that photos are filled with
+    fn provider(&self) -> Option<Rc<dyn SampleProvider>> {
+        let textures: Rc<dyn SampleProvider> = Rc::new(self.textures.clone());
+        match &self.library {
+            _ if self.only_textures && !self.textures.is_empty() => Some(textures),
+            Some(library) if self.textures.is_empty() => Some(library.clone()),
+            Some(library) => Some(Rc::new(CombinedProvider::new(vec![textures, library.clone()]))),
+            None => None,
+        }
+    }
+
+    // opens the active packs that are not opened yet, a pack that cannot be opened
+    // gets generated textures
+    fn open_packs(&mut self, ctx: &Context<Self>) {
+        for pack in self.packs.iter_mut().filter(|pack| pack.active && pack.provider.is_none() && !pack.opening) {
+            pack.opening = true;
+            let name = pack.info.name.clone();
+            let url = format!("{}/{}", STATIC_URL, pack.info.dir);
+            ctx.link().send_future(async move {
+                match HttpProvider::open(&url).await {
+                    Ok(provider) => Msg::PackOpened(name, Rc::new(provider)),
+                    Err(error) => {
+                        log::error!("Cannot load sample pack {}, using generated textures: {:?}", name, error);
+                        Msg::PackOpened(name, Rc::new(ProceduralProvider::default()))
+                    }
+                }
+            });
+        }
+    }
+
+    // combines the active packs, when they are all opened
+    fn update_library(&mut self, ctx: &Context<Self>) {
+        let active = self.packs.iter().filter(|pack| pack.active).count();
+        let opened: Vec<(PackWeight, Rc<dyn SampleProvider>)> = self.packs
+            .iter()
+            .filter(|pack| pack.active)
+            .filter_map(|pack| pack.provider.clone().map(|provider| (PackWeight::new(&pack.info.name, pack.weight), provider)))
+            .collect();
+        self.library = if active > 0 && opened.len() == active {
+            Some(Rc::new(CombinedProvider::packs(opened)))
+        } else {
+            None
+        };
+        if self.library.is_some() {
+            self.refresh(ctx);
+        }
+    }
+
+    fn view_pack(&self, ctx: &Context<Self>, pack: &Pack) -> Html {
+        let name = pack.info.name.clone();
+        let toggled = ctx.link().callback(move |e: Event| Msg::PackToggled(name.clone(), e.target_unchecked_into::<HtmlInputElement>().checked()));
+        let name = pack.info.name.clone();
+        let weighted = ctx.link().callback(move |e: Event| Msg::PackWeighted(name.clone(), e.target_unchecked_into::<HtmlInputElement>().value_as_number() as f32));
+        html! {
+            <label class="pack">
+                <input type="checkbox" checked={pack.active} onchange={toggled}/>
+                { pack.info.name.clone() }
+                <input type="number" min="0.1" step="0.1" value={pack.weight.to_string()} onchange={weighted}/>
+            </label>
+        }
+    }
+
+    // Fetches all missing samples of the sample fill at stage fill at once, the transformation
+    // continues when they are in.
+    fn load_samples(&mut self, ctx: &Context<Self>, provider: Rc<dyn SampleProvider>, fill: usize, image: RgbImage, mut filter_ctx: FilterContext) {
+        let missing = self.pipeline.gather_samples(fill, &image, &mut filter_ctx, &mut self.samples);
+        let generation = self.generation;
+        ctx.link().send_future(async move {
+            let mut loaded = vec![];
+            for sample in provider.load_all(&missing).await {
+                match sample {
+                    Ok(sample) => loaded.push(sample),
+                    Err(error) => log::error!("Cannot load sample: {}", error),
+                }
+            }
+            Msg::SamplesLoaded(generation, fill, image, filter_ctx, loaded)
+        });
+    }
+
+    fn draw(&self, transformed: &RgbImage) {
+        if let Some(canvas) = get_canvas("dest") {
+            interop::write_canvas(&canvas, &interop::to_rgba(transformed)).expect("Cannot draw result on canvas");
+        }
+    }
+
+    // transforms the photo again, with the current samples
+    fn refresh(&self, ctx: &Context<Self>) {
+        if self.has_photo {
+            ctx.link().send_message(Msg::ImageLoaded);
+        }
+    }
 }
 
 impl Component for DropPhoto {
     type Message = Msg;
     type Properties = ();
 
-    fn create(_ctx: &Context<Self>) -> Self {
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_future(async {
+            match http::fetch_packs(STATIC_URL).await {
+                Ok(packs) => Msg::PacksReady(packs),
+                Err(error) => {
+                    log::error!("Cannot load the list of sample packs, using {}: {:?}", DEFAULT_PACK, error);
+                    Msg::PacksReady(PackList { packs: vec![PackInfo { name: DEFAULT_PACK.to_owned(), dir: DEFAULT_PACK.to_owned() }] })
+                }
+            }
+        });
         Self {
-            samples: Samples::new()
+            samples: SampleStore::default(),
+            pipeline: Pipeline::default(),
+            filters: FilterRegistry::default(),
+            packs: vec![],
+            library: None,
+            textures: MemoryProvider::new(),
+            only_textures: false,
+            has_photo: false,
+            generation: 0,
         }
     }
 
-    fn update(& mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
+    fn update(& mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::Dragged(event) => {
                 event.prevent_default();
@@ -56,36 +220,129 @@ impl Component for DropPhoto {
                 }
                 true
             }
+            Msg::PacksReady(list) => {
+                self.packs = list.packs.into_iter().map(|info| Pack { info, weight: 1.0, active: true, provider: None, opening: false }).collect();
+                self.open_packs(ctx);
+                true
+            }
+            Msg::PackOpened(name, provider) => {
+                if let Some(pack) = self.packs.iter_mut().find(|pack| pack.info.name == name) {
+                    pack.provider = Some(provider);
+                    pack.opening = false;
+                }
+                self.update_library(ctx);
+                false
+            }
+            Msg::PackToggled(name, active) => {
+                if let Some(pack) = self.packs.iter_mut().find(|pack| pack.info.name == name) {
+                    pack.active = active;
+                }
+                self.open_packs(ctx);
+                self.update_library(ctx);
+                true
+            }
+            Msg::PackWeighted(name, weight) => {
+                match self.packs.iter_mut().find(|pack| pack.info.name == name) {
+                    Some(pack) if weight.is_finite() && weight > 0.0 => pack.weight = weight,
+                    _ => return true,
+                }
+                self.update_library(ctx);
+                true
+            }
             Msg::ImageLoaded => {
-                if let Some(canvas) = document().get_element_by_id("source").and_then(|e| e.dyn_into::<HtmlCanvasElement>().ok()) {
-                    let ctx: CanvasRenderingContext2d = canvas
-                        .get_context("2d")
-                        .unwrap()
-                        .unwrap()
-                        .dyn_into::<CanvasRenderingContext2d>()
-                        .unwrap();
-                    if let Some(img) = document().get_element_by_id("source-image").and_then(|e| e.dyn_into::<HtmlImageElement>().ok()) {
-                        canvas.set_width(img.width());
-                        canvas.set_height(img.height());
-                        ctx.draw_image_with_html_image_element(&img, 0.0, 0.0).expect("Cannot draw image on canvas");
-                    }
+                self.has_photo = true;
+                let provider = match self.provider() {
+                    Some(provider) => provider,
+                    // the photo is transformed once the packs are opened
+                    None => return false,
+                };
+                if let (Some(canvas), Some(img)) = (get_canvas("source"), document().get_element_by_id("source-image").and_then(|e| e.dyn_into::<HtmlImageElement>().ok())) {
+                    let rgba_src = interop::read_image_element(&canvas, &img).expect("Cannot draw image on canvas");
                     if let Some(drop_zone) = document().get_element_by_id("drop-zone") {
                         drop_zone.set_attribute("style", "display:none").expect("Cannot update attribute");
                     }
+                    let rgb_src = interop::to_rgb(&rgba_src, interop::BACKGROUND);
 
-                    let imgdata = ctx
-                        .get_image_data(0.0, 0.0, canvas.width() as f64, canvas.height() as f64)
-                        .unwrap();
-                    let raw_pixels: Vec<u8> = imgdata.data().to_vec();
-                    let rgb_src = RgbImage::from_raw(canvas.width(), canvas.height(), raw_pixels).unwrap();
-                    let transformed = transform::apply(&rgb_src).expect("Cannot transform image");
-                    let image_data = ImageData::new_with_u8_clamped_array_and_sh(Clamped(&rgb_src.to_vec()),
-                                                                          canvas.width(), canvas.height());
-
-                    ctx.put_image_data(&image_data.expect(""), 0.0, 0.0);
+                    let mut filter_ctx = FilterContext::new(&rgb_src, provider.catalog());
+                    let prepared = match self.pipeline.prepare(&rgb_src, &mut filter_ctx, &self.filters) {
+                        Ok(prepared) => prepared,
+                        Err(error) => {
+                            log::error!("Cannot transform image: {}", error);
+                            return true;
+                        }
+                    };
+                    self.generation += 1;
+                    match self.pipeline.next_fill(0) {
+                        Some(fill) => self.load_samples(ctx, provider, fill, prepared, filter_ctx),
+                        None => self.draw(&prepared),
+                    }
+                    // #source keeps the photo as drawn, the result goes to #dest
                 }
                 true
             }
+            Msg::SamplesLoaded(generation, fill, image, mut filter_ctx, loaded) => {
+                for sample in loaded {
+                    filter_ctx.add_sample(self.samples.insert(sample));
+                }
+                // A later transformation started while these samples were loading. They are in
+                // the store for the runs after it, that one already asked for its own copies.
+                if generation != self.generation {
+                    return false;
+                }
+                let stats = self.samples.stats();
+                log::info!("samples: {} loaded, {} from cache, {} evicted, {} MiB in use",
+                    stats.misses, stats.hits, stats.evictions, self.samples.used() >> 20);
+                let provider = match self.provider() {
+                    Some(provider) => provider,
+                    None => return false,
+                };
+                match self.pipeline.resume(fill, image, &mut filter_ctx, &self.filters) {
+                    // the next sample fill needs its samples first
+                    Ok(image) => match self.pipeline.next_fill(fill + 1) {
+                        Some(next) => self.load_samples(ctx, provider, next, image, filter_ctx),
+                        None => self.draw(&image),
+                    },
+                    Err(error) => log::error!("Cannot transform image: {}", error),
+                }
+                true
+            }
+            Msg::TexturesDropped(event) => {
+                event.prevent_default();
+                let data_transfer = event
+                    .data_transfer()
+                    .expect("Event should have DataTransfer");
+                let item_list = data_transfer.items();
+                for i in 0..item_list.length() {
+                    let item = item_list.get(i).expect("Should find an item");
+                    if item.kind() == "file" {
+                        if let Ok(Some(file)) = item.get_as_file() {
+                            ctx.link().send_future(async move {
+                                Msg::TextureLoaded(file.name(), load_texture(&file).await)
+                            });
+                        }
+                    }
+                }
+                false
+            }
+            Msg::TextureLoaded(name, Ok(image)) => {
+                // the entry of the texture is measured like the samples of the library,
+                // its id keeps it apart from them
+                let entry = library::entry(&self.textures.catalog(), &image, &[format!("{}:user", tags::SOURCE)]);
+                let entry = SampleEntry { id: format!("user-{}", self.textures.len() + 1), file: name, ..entry };
+                log::info!("texture {} ({}) has mean color {:?}", entry.id, entry.file, entry.mean);
+                self.textures.insert(entry, image);
+                self.refresh(ctx);
+                true
+            }
+            Msg::TextureLoaded(name, Err(error)) => {
+                log::error!("Cannot load texture {}: {:?}", name, error);
+                false
+            }
+            Msg::OnlyTextures(only_textures) => {
+                self.only_textures = only_textures;
+                self.refresh(ctx);
+                true
+            }
         }
     }
 
@@ -94,10 +351,23 @@ impl Component for DropPhoto {
         html! {
             <>
             <div id="drop-zone" class="drop-zone"
-                ondragover={link.callback(|e| Msg::Dragged(e))}
-                ondrop={link.callback(|e| Msg::Dropped(e))}>
+                ondragover={link.callback(Msg::Dragged)}
+                ondrop={link.callback(Msg::Dropped)}>
                 <p>{ "drag your photos here" }</p>
             </div>
+            <div id="texture-zone" class="drop-zone texture-zone"
+                ondragover={link.callback(Msg::Dragged)}
+                ondrop={link.callback(Msg::TexturesDropped)}>
+                <p>{ "drop your own textures here" }</p>
+            </div>
+            <div class="packs">
+                { for self.packs.iter().map(|pack| self.view_pack(ctx, pack)) }
+            </div>
+            <label class="textures">
+                <input type="checkbox" checked={self.only_textures}
+                    onchange={link.callback(|e: Event| Msg::OnlyTextures(e.target_unchecked_into::<HtmlInputElement>().checked()))}/>
+                { format!("only my textures ({})", self.textures.len()) }
+            </label>
             <img id="source-image" style="display:none" onload={link.callback(|_| Msg::ImageLoaded)}/>
             <canvas id="source"></canvas>
             <canvas id="dest"></canvas>
@@ -108,42 +378,14 @@ impl Component for DropPhoto {
     }
 }
 
-// if the transformer needs a new sample, it uses HtmlImageElement to download it.
-// all is async
-pub fn add_sample(name: &'static str) {
-    let sample = document().create_element("img").unwrap().dyn_into::<HtmlImageElement>().expect("Cannot create img element");
-    sample.set_src(&format!("/static/samples/{}.jpg", name));
-    let samples = document().get_element_by_id("samples").unwrap();
-
-    let image_loaded = Closure::wrap(Box::new( |_: web_sys::Event| {
-        if let Some(canvas) = document().get_element_by_id("buffer").and_then(|e| e.dyn_into::<HtmlCanvasElement>().ok()) {
-            let ctx: CanvasRenderingContext2d = canvas
-                .get_context("2d")
-                .unwrap()
-                .unwrap()
-                .dyn_into::<CanvasRenderingContext2d>()
-                .unwrap();
-            canvas.set_width(sample.width());
-            canvas.set_height(sample.height());
-            ctx.draw_image_with_html_image_element(&sample, 0.0, 0.0).expect("Cannot draw image on canvas");
-            let imgdata = ctx
-                .get_image_data(0.0, 0.0, canvas.width() as f64, canvas.height() as f64)
-                .unwrap();
-            let raw_pixels: Vec<u8> = imgdata.data().to_vec();
-            let sample = RgbImage::from_raw(canvas.width(), canvas.height(), raw_pixels).unwrap();
-            samples::insert(name.to_owned(), ColorSample::new(&name, sample));
-        }
-    }) as Box<dyn FnMut(_)>);
-    sample.add_event_listener_with_callback("load", image_loaded.as_ref().unchecked_ref()).expect("cannot add onload listener");
-    samples.append_child(&sample);
+// decodes a dropped texture on the #buffer canvas
+async fn load_texture(file: &File) -> Result<RgbImage, JsValue> {
+    let url = Url::create_object_url_with_blob(file)?;
+    let img = http::load_image(&url).await;
+    Url::revoke_object_url(&url)?;
+    let img = img?;
+    let canvas = get_canvas("buffer").ok_or_else(|| JsValue::from_str("cannot get #buffer"))?;
+    let image = interop::read_image_element(&canvas, &img);
+    img.remove();
+    Ok(library::shrink(&interop::to_rgb(&image?, interop::BACKGROUND), TEXTURE_SIZE))
 }
-
-fn create_element<'a, T>(element_type: &str) -> T
-    where
-        T: JsCast,
-{
-    let element = document().create_element(element_type).unwrap();
-    element
-        .dyn_into::<T>()
-        .expect(&format!("Cannot create element {}", element_type))
-}
\ No newline at end of file
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use futures::future::{join, join_all, LocalBoxFuture};
+use gloo_utils::{document, window};
+use js_sys::Promise;
+use wasm_bindgen::JsCast;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlImageElement, Response};
+
+use spiegel_core::catalog::{Catalog, SampleEntry};
+use spiegel_core::error::SpiegelError;
+use spiegel_core::pack::{PackList, PACKS_FILE};
+use spiegel_core::provider::SampleProvider;
+use spiegel_core::samples::ColorSample;
+
+use crate::interop;
+
+// fewer samples than this are downloaded from their own files rather than from their atlas page
+const MIN_PAGE_SAMPLES: usize = 4;
+
+// The sample library on the web server: a manifest.json with the images next to it.
+// Images are downloaded with HtmlImageElement and decoded on the #buffer canvas, samples
+// in the atlas are cut out of its pages. The img elements are removed once their pixels are read,
+// the samples are kept in the SampleStore.
+pub struct HttpProvider {
+    // url of the directory with the manifest, without the trailing slash
+    base: String,
+    catalog: Arc<Catalog>,
+}
+
+impl HttpProvider {
+    pub async fn open(base: &str) -> Result<Self, JsValue> {
+        let json = fetch_text(&format!("{}/manifest.json", base)).await?;
+        let catalog = Catalog::from_json(&json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Self { base: base.to_owned(), catalog: Arc::new(catalog) })
+    }
+
+    // an atlas page, the caller removes the img once it has cut out its samples
+    async fn load_page(&self, page: usize) -> Result<HtmlImageElement, JsValue> {
+        let file = self.catalog.atlas.get(page).ok_or_else(|| JsValue::from_str("no such atlas page"))?;
+        load_image(&format!("{}/{}", self.base, file)).await
+    }
+
+    // a sample that is not in the atlas
+    async fn load_file(&self, entry: &SampleEntry) -> Result<ColorSample, JsValue> {
+        let img = load_image(&format!("{}/{}", self.base, entry.file)).await?;
+        // the image is decoded, nothing is awaited from here on, so the buffer canvas is ours
+        let canvas = interop::get_canvas("buffer").ok_or_else(|| JsValue::from_str("cannot get #buffer"))?;
+        let image = interop::read_image_element(&canvas, &img);
+        img.remove();
+        Ok(ColorSample::new(entry, interop::to_rgb(&image?, interop::BACKGROUND)))
+    }
+}
+
+impl SampleProvider for HttpProvider {
+    fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.clone()
+    }
+
+    fn load<'a>(&'a self, entry: &'a SampleEntry) -> LocalBoxFuture<'a, Result<ColorSample, SpiegelError>> {
+        Box::pin(async move {
+            let sample = match entry.atlas.filter(|_| self.catalog.page(entry).is_some()) {
+                Some(rect) => match self.load_page(rect.page).await {
+                    Ok(page) => {
+                        let sample = slice(&page, entry);
+                        page.remove();
+                        sample
+                    }
+                    Err(error) => Err(error),
+                },
+                None => self.load_file(entry).await,
+            };
+            sample.map_err(|error| load_error(entry, error))
+        })
+    }
+
+    // Every atlas page is downloaded once, however many of its samples are needed, and all
+    // downloads run concurrently. The samples of a page that would supply only a few of them
+    // are downloaded from their own files instead.
+    fn load_all<'a>(&'a self, entries: &'a [SampleEntry]) -> LocalBoxFuture<'a, Vec<Result<ColorSample, SpiegelError>>> {
+        Box::pin(async move {
+            let page_of = |entry: &SampleEntry| self.catalog.page(entry).and(entry.atlas).map(|rect| rect.page);
+            let mut pages: Vec<usize> = entries.iter().filter_map(page_of).collect();
+            pages.sort_unstable();
+            let pages: Vec<usize> = pages
+                .chunk_by(|a, b| a == b)
+                .filter(|same| same.len() >= MIN_PAGE_SAMPLES)
+                .map(|same| same[0])
+                .collect();
+            let (in_atlas, separate): (Vec<&SampleEntry>, Vec<&SampleEntry>) = entries
+                .iter()
+                .partition(|entry| page_of(*entry).is_some_and(|page| pages.contains(&page)));
+
+            let (loaded, mut samples) = join(
+                join_all(pages.iter().map(|page| self.load_page(*page))),
+                join_all(separate.into_iter().map(|entry| async move {
+                    self.load_file(entry).await.map_err(|error| load_error(entry, error))
+                })),
+            ).await;
+            let loaded: Vec<(usize, Result<HtmlImageElement, JsValue>)> = pages.into_iter().zip(loaded).collect();
+            for entry in in_atlas {
+                let page = loaded.iter().find(|(page, _)| Some(*page) == entry.atlas.map(|rect| rect.page));
+                let sample = match page {
+                    Some((_, Ok(page))) => slice(page, entry),
+                    Some((_, Err(error))) => Err(error.clone()),
+                    None => Err(JsValue::from_str("atlas page is not loaded")),
+                };
+                samples.push(sample.map_err(|error| load_error(entry, error)));
+            }
+            for page in loaded.iter().filter_map(|(_, page)| page.as_ref().ok()) {
+                page.remove();
+            }
+            samples
+        })
+    }
+}
+
+// the packs.json in base
+pub async fn fetch_packs(base: &str) -> Result<PackList, JsValue> {
+    let json = fetch_text(&format!("{}/{}", base, PACKS_FILE)).await?;
+    PackList::from_json(&json).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+async fn fetch_text(url: &str) -> Result<String, JsValue> {
+    let response: Response = JsFuture::from(window().fetch_with_str(url)).await?.dyn_into()?;
+    if !response.ok() {
+        return Err(JsValue::from_str(&format!("{}: {}", url, response.status())));
+    }
+    let text = JsFuture::from(response.text()?).await?;
+    text.as_string().ok_or_else(|| JsValue::from_str(&format!("{} is not text", url)))
+}
+
+fn slice(page: &HtmlImageElement, entry: &SampleEntry) -> Result<ColorSample, JsValue> {
+    let rect = entry.atlas.ok_or_else(|| JsValue::from_str("not in the atlas"))?;
+    let canvas = interop::get_canvas("buffer").ok_or_else(|| JsValue::from_str("cannot get #buffer"))?;
+    let image = interop::read_image_rect(&canvas, page, rect.x, rect.y, rect.width, rect.height)?;
+    Ok(ColorSample::new(entry, interop::to_rgb(&image, interop::BACKGROUND)))
+}
+
+fn load_error(entry: &SampleEntry, error: JsValue) -> SpiegelError {
+    SpiegelError::SampleLoad(entry.id.clone(), format!("{:?}", error).into())
+}
+
+// Uses HtmlImageElement to download and decode an image, the future completes when it is loaded.
+// The caller removes the img when it is done with it, an img that fails to load is removed here.
+pub(crate) async fn load_image(src: &str) -> Result<HtmlImageElement, JsValue> {
+    let img = append_image(src)?;
+    if let Err(error) = loaded(&img).await {
+        img.remove();
+        return Err(error);
+    }
+    Ok(img)
+}
+
+// an img in #samples that starts downloading src
+fn append_image(src: &str) -> Result<HtmlImageElement, JsValue> {
+    let img = create_element::<HtmlImageElement>("img");
+    img.set_src(src);
+    let samples = document().get_element_by_id("samples").unwrap();
+    samples.append_child(&img)?;
+    Ok(img)
+}
+
+// completes when img is loaded
+async fn loaded(img: &HtmlImageElement) -> Result<(), JsValue> {
+    let loaded = Promise::new(&mut |resolve, reject| {
+        let _ = img.add_event_listener_with_callback("load", &resolve);
+        let _ = img.add_event_listener_with_callback("error", &reject);
+    });
+    JsFuture::from(loaded).await?;
+    Ok(())
+}
+
+fn create_element<'a, T>(element_type: &str) -> T
+    where
+        T: JsCast,
+{
+    let element = document().create_element(element_type).unwrap();
+    element
+        .dyn_into::<T>()
+        .expect(&format!("Cannot create element {}", element_type))
+}
@@ -0,0 +1,115 @@
+use gloo_utils::document;
+use image::{Pixel, Rgb, RgbImage, Rgba, RgbaImage};
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlImageElement, ImageData};
+
+// Conversions between the canvas world, where pixels are always RGBA, and the image crate.
+// All canvas reads and writes go through here, so that no RGBA bytes end up in an RgbImage.
+
+// the background that transparent pixels are blended onto when alpha is dropped
+pub const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+
+pub fn context_2d(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d, JsValue> {
+    canvas
+        .get_context("2d")?
+        .ok_or_else(|| JsValue::from_str("canvas has no 2d context"))?
+        .dyn_into::<CanvasRenderingContext2d>()
+        .map_err(JsValue::from)
+}
+
+pub fn from_image_data(image_data: &ImageData) -> Result<RgbaImage, JsValue> {
+    RgbaImage::from_raw(image_data.width(), image_data.height(), image_data.data().0)
+        .ok_or_else(|| JsValue::from_str("image data does not match its dimensions"))
+}
+
+pub fn to_image_data(image: &RgbaImage) -> Result<ImageData, JsValue> {
+    ImageData::new_with_u8_clamped_array_and_sh(Clamped(image.as_raw().as_slice()), image.width(), image.height())
+}
+
+// reads the full content of the canvas
+pub fn read_canvas(canvas: &HtmlCanvasElement) -> Result<RgbaImage, JsValue> {
+    let ctx = context_2d(canvas)?;
+    let image_data = ctx.get_image_data(0.0, 0.0, canvas.width() as f64, canvas.height() as f64)?;
+    from_image_data(&image_data)
+}
+
+// resizes the canvas to the image and draws it
+pub fn write_canvas(canvas: &HtmlCanvasElement, image: &RgbaImage) -> Result<(), JsValue> {
+    canvas.set_width(image.width());
+    canvas.set_height(image.height());
+    context_2d(canvas)?.put_image_data(&to_image_data(image)?, 0.0, 0.0)
+}
+
+// decodes a loaded img element by drawing it on the (scratch) canvas and reading it back
+pub fn read_image_element(canvas: &HtmlCanvasElement, img: &HtmlImageElement) -> Result<RgbaImage, JsValue> {
+    canvas.set_width(img.natural_width());
+    canvas.set_height(img.natural_height());
+    context_2d(canvas)?.draw_image_with_html_image_element(img, 0.0, 0.0)?;
+    read_canvas(canvas)
+}
+
+// decodes a part of a loaded img element, for images that hold many others
+pub fn read_image_rect(canvas: &HtmlCanvasElement, img: &HtmlImageElement, x: u32, y: u32, width: u32, height: u32) -> Result<RgbaImage, JsValue> {
+    canvas.set_width(width);
+    canvas.set_height(height);
+    context_2d(canvas)?.draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+        img, x as f64, y as f64, width as f64, height as f64, 0.0, 0.0, width as f64, height as f64,
+    )?;
+    read_canvas(canvas)
+}
+
+pub fn get_canvas(id: &str) -> Option<HtmlCanvasElement> {
+    document().get_element_by_id(id).and_then(|e| e.dyn_into::<HtmlCanvasElement>().ok())
+}
+
+// drops the alpha channel by blending every pixel onto the background
+pub fn to_rgb(image: &RgbaImage, background: Rgb<u8>) -> RgbImage {
+    let mut rgb = RgbImage::new(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let alpha = pixel[3] as u32;
+        let mut blended = [0u8; 3];
+        for (i, channel) in blended.iter_mut().enumerate() {
+            let value = pixel[i] as u32 * alpha + background[i] as u32 * (255 - alpha);
+            *channel = ((value + 127) / 255) as u8;
+        }
+        rgb.put_pixel(x, y, Rgb(blended));
+    }
+    rgb
+}
+
+// adds a fully opaque alpha channel
+pub fn to_rgba(image: &RgbImage) -> RgbaImage {
+    let mut rgba = RgbaImage::new(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let channels = pixel.channels();
+        rgba.put_pixel(x, y, Rgba([channels[0], channels[1], channels[2], 255]));
+    }
+    rgba
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip_keeps_opaque_pixels() {
+        let mut rgb = RgbImage::new(2, 1);
+        rgb.put_pixel(0, 0, Rgb([10, 20, 30]));
+        rgb.put_pixel(1, 0, Rgb([200, 100, 0]));
+
+        let rgba = to_rgba(&rgb);
+        assert_eq!(&Rgba([200, 100, 0, 255]), rgba.get_pixel(1, 0));
+        assert_eq!(rgb, to_rgb(&rgba, BACKGROUND));
+    }
+
+    #[test]
+    fn alpha_is_blended_onto_background() {
+        let mut rgba = RgbaImage::new(2, 1);
+        rgba.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        rgba.put_pixel(1, 0, Rgba([0, 0, 0, 128]));
+
+        let rgb = to_rgb(&rgba, Rgb([255, 255, 255]));
+        assert_eq!(&Rgb([255, 255, 255]), rgb.get_pixel(0, 0));
+        assert_eq!(&Rgb([127, 127, 127]), rgb.get_pixel(1, 0));
+    }
+}
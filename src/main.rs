@@ -1,9 +1,6 @@
-extern crate lazy_static;
-
 mod app;
-mod transform;
-mod quantizer;
-mod samples;
+mod http;
+mod interop;
 
 fn main() {
     wasm_logger::init(wasm_logger::Config::default());